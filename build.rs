@@ -0,0 +1,19 @@
+fn main() {
+    // The gRPC service is generated from `proto/esgea.proto`, with a pure-Rust protobuf
+    // compiler so that building doesn't need `protoc` installed.
+    #[cfg(feature = "grpc")]
+    {
+        use prost::Message;
+
+        println!("cargo:rerun-if-changed=proto");
+        let fds = protox::compile(["esgea.proto"], ["proto"]).expect("compiling proto/esgea.proto");
+        let path = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("esgea.bin");
+        std::fs::write(&path, fds.encode_to_vec()).expect("writing the descriptor set");
+        tonic_build::configure()
+            .build_client(false)
+            .file_descriptor_set_path(&path)
+            .skip_protoc_run()
+            .compile(&["esgea.proto"], &["proto"])
+            .expect("generating the gRPC service");
+    }
+}
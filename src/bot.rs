@@ -0,0 +1,109 @@
+use std::collections::VecDeque;
+
+use petgraph::graph::NodeIndex;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{Action, Game, GameView, IntelKind, PlayerId};
+
+/// How hard a computer player tries.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BotLevel {
+    /// Wanders, grabbing whatever it stands on.
+    Easy,
+    /// Expands toward unclaimed ground and strikes opponents it knows are beside it.
+    #[default]
+    Normal,
+    /// As `Normal`, and also spends intel hunting opponents and garrisoning what it holds.
+    Hard,
+}
+
+impl Game {
+    /// An action for computer player `pid`, chosen only from what `pid` knows (see
+    /// `view_for`), so bots play under the same fog as everyone else.
+    ///
+    /// The action may still fail, e.g. capturing a garrisoned location the bot didn't know
+    /// was held; callers should fall back to `Action::Wait`.
+    pub fn bot_action(&self, pid: PlayerId, level: BotLevel, rng: &mut impl Rng) -> Action {
+        let Ok(view) = self.view_for(pid) else {
+            return Action::Wait;
+        };
+        let here = view.me.location;
+        let mine = |at: NodeIndex| view.locations[at.index()].control == Some(pid);
+
+        if level == BotLevel::Easy {
+            if !mine(here) && rng.gen_bool(0.5) {
+                return Action::Capture;
+            }
+            return match view.locations[here.index()].neighbors.choose(rng) {
+                Some(&to) if rng.gen_bool(0.7) => Action::Move(to),
+                _ => Action::Wait,
+            };
+        }
+
+        let beside = view
+            .opponents
+            .iter()
+            .any(|o| !o.resigned && o.last_seen == Some(here) && o.age == Some(0));
+        if beside {
+            return Action::Strike;
+        }
+        if !mine(here) {
+            return Action::Capture;
+        }
+        if level == BotLevel::Hard {
+            let intel = view.me.intel;
+            let stale = view
+                .opponents
+                .iter()
+                .filter(|o| !o.resigned)
+                .find(|o| o.age.is_none_or(|age| age > 2));
+            if let Some(o) = stale.filter(|_| intel >= self.cost(IntelKind::Reveal)) {
+                return Action::Reveal(o.id);
+            }
+            let garrison = view.locations[here.index()].garrison.unwrap_or(0);
+            if garrison < self.rules.max_garrison && intel > self.cost(IntelKind::Reinforce) + 1 {
+                return Action::Reinforce;
+            }
+        }
+        match step_toward_unclaimed(&view, pid) {
+            Some(to) => Action::Move(to),
+            None => Action::Wait,
+        }
+    }
+}
+
+/// First step along a shortest path to the nearest location `pid` doesn't believe it holds,
+/// preferring richer locations among the nearest.
+fn step_toward_unclaimed(view: &GameView, pid: PlayerId) -> Option<NodeIndex> {
+    let start = view.me.location;
+    let mut first_step = vec![None; view.locations.len()];
+    let mut seen = vec![false; view.locations.len()];
+    seen[start.index()] = true;
+    let mut frontier = VecDeque::from([start]);
+    while !frontier.is_empty() {
+        let mut found: Option<(NodeIndex, u32)> = None;
+        for _ in 0..frontier.len() {
+            let at = frontier.pop_front()?;
+            for &next in &view.locations[at.index()].neighbors {
+                if seen[next.index()] {
+                    continue;
+                }
+                seen[next.index()] = true;
+                first_step[next.index()] = first_step[at.index()].or(Some(next));
+                let loc = &view.locations[next.index()];
+                if loc.control != Some(pid) && found.is_none_or(|(_, best)| loc.base_income > best)
+                {
+                    found = Some((next, loc.base_income));
+                }
+                frontier.push_back(next);
+            }
+        }
+        if let Some((target, _)) = found {
+            return first_step[target.index()];
+        }
+    }
+    None
+}
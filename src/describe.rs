@@ -0,0 +1,182 @@
+use std::fmt;
+
+use petgraph::graph::NodeIndex;
+use serde::Serialize;
+
+use crate::{IntelKind, Observation, Perception, PlayerId};
+
+/// A language-neutral account of an observation: a stable message key plus typed parameters.
+///
+/// Clients look the key up in their own message catalog; `Display` renders the default English.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Description {
+    pub key: &'static str,
+    pub params: Vec<(&'static str, Param)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Param {
+    Player(PlayerId),
+    /// An actor the observer could not identify.
+    Someone,
+    Location(NodeIndex),
+    Number(u32),
+    Intel(IntelKind),
+    Perception(Perception),
+    Text(String),
+}
+
+impl Observation {
+    pub fn describe(&self) -> Description {
+        use Param::*;
+        let actor = |pid: &Option<PlayerId>| pid.map_or(Someone, Player);
+        let (key, params) = match self.clone() {
+            Observation::Death { by, of } => {
+                ("death", vec![("by", Player(by)), ("of", Player(of))])
+            }
+            Observation::Strike { by, at, prepared } => {
+                let key = match (prepared, at.is_some()) {
+                    (false, false) => "strike",
+                    (false, true) => "strike.located",
+                    (true, false) => "strike.prepared",
+                    (true, true) => "strike.prepared.located",
+                };
+                let mut params = vec![("by", actor(&by))];
+                params.extend(at.map(|at| ("at", Location(at))));
+                (key, params)
+            }
+            Observation::WaitMove { by } => ("wait_move", vec![("by", actor(&by))]),
+            Observation::Capture { by, at } => {
+                ("capture", vec![("by", Player(by)), ("at", Location(at))])
+            }
+            Observation::Intel { by, kind } => match kind {
+                Some(kind) => (
+                    "intel.kind",
+                    vec![("by", actor(&by)), ("kind", Intel(kind))],
+                ),
+                None => ("intel", vec![("by", actor(&by))]),
+            },
+            Observation::Reveal { who, at } => {
+                ("reveal", vec![("who", Player(who)), ("at", Location(at))])
+            }
+            Observation::RevealFailure { who } => ("reveal_failure", vec![("who", Player(who))]),
+            Observation::Garrison { at, strength } => (
+                "garrison",
+                vec![("at", Location(at)), ("strength", Number(strength))],
+            ),
+            Observation::Alert { level } => ("alert", vec![("level", Number(level))]),
+            Observation::Resign { who } => ("resign", vec![("who", Player(who))]),
+            Observation::Sighting { who, at, as_of } => (
+                "sighting",
+                vec![
+                    ("who", Player(who)),
+                    ("at", Location(at)),
+                    ("turn", Number(as_of)),
+                ],
+            ),
+            Observation::Rumor { who, at: [a, b] } => (
+                "rumor",
+                vec![
+                    ("who", Player(who)),
+                    ("at", Location(a)),
+                    ("alt", Location(b)),
+                ],
+            ),
+            Observation::Move { who, from, to, how } => {
+                let key = match (from, to) {
+                    (Some(_), Some(_)) => "move",
+                    (Some(_), None) => "move.from",
+                    (None, Some(_)) => "move.to",
+                    (None, None) => "move.unknown",
+                };
+                let mut params = vec![("how", Perception(how)), ("who", Player(who))];
+                params.extend(from.map(|from| ("from", Location(from))));
+                params.extend(to.map(|to| ("to", Location(to))));
+                (key, params)
+            }
+            Observation::TurnStart { who, actions } => (
+                "turn_start",
+                vec![("who", Player(who)), ("actions", Number(actions))],
+            ),
+            Observation::TimedOut { who } => ("timed_out", vec![("who", Player(who))]),
+            Observation::Custom { key, data } => (
+                "custom",
+                vec![("name", Text(key)), ("data", Text(data.to_string()))],
+            ),
+        };
+        Description { key, params }
+    }
+}
+
+impl Description {
+    /// The default English template for a message key.
+    fn template(key: &str) -> &'static str {
+        match key {
+            "death" => "{by} killed {of}",
+            "strike" => "{by} launched a strike somewhere",
+            "strike.located" => "{by} launched a strike at {at}",
+            "strike.prepared" => "{by} launched a prepared strike somewhere",
+            "strike.prepared.located" => "{by} launched a prepared strike at {at}",
+            "wait_move" => "{by} waited or moved",
+            "capture" => "{by} captured {at}",
+            "intel" => "{by} spent intel",
+            "intel.kind" => "{by} spent intel on {kind}",
+            "reveal" => "{who} is at {at}",
+            "reveal_failure" => "could not find {who}",
+            "garrison" => "the garrison at {at} is now {strength} strong",
+            "alert" => "the alert level is now {level}",
+            "resign" => "{who} resigned",
+            "sighting" => "an informant places {who} at {at} as of turn {turn}",
+            "rumor" => "unconfirmed: {who} may be at {at} or {alt}",
+            "move" => "{how} {who} move from {from} to {to}",
+            "move.from" => "{how} {who} leave {from}",
+            "move.to" => "{how} {who} arrive at {to}",
+            "move.unknown" => "{how} {who} moving",
+            "turn_start" => "{who}'s turn, with {actions} actions",
+            "timed_out" => "{who} ran out of time",
+            "custom" => "{name}: {data}",
+            _ => "{key}",
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Param::Player(pid) => write!(f, "player {pid}"),
+            Param::Someone => write!(f, "someone"),
+            Param::Location(at) => write!(f, "{}", at.index()),
+            Param::Number(n) => write!(f, "{n}"),
+            Param::Intel(kind) => write!(f, "{kind:?}"),
+            Param::Perception(Perception::Seen) => write!(f, "saw"),
+            Param::Perception(Perception::Heard) => write!(f, "heard"),
+            Param::Perception(Perception::Scanned) => write!(f, "scanned"),
+            Param::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+impl Description {
+    /// Render in English, calling players whatever `name` says.
+    pub fn english(&self, name: impl Fn(PlayerId) -> String) -> String {
+        let mut text = Description::template(self.key).replace("{key}", self.key);
+        for (key, param) in &self.params {
+            let value = match param {
+                Param::Player(pid) => name(*pid),
+                _ => param.to_string(),
+            };
+            text = text.replace(&format!("{{{key}}}"), &value);
+        }
+        let mut chars = text.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => text,
+        }
+    }
+}
+
+impl fmt::Display for Description {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.english(|pid| Param::Player(pid).to_string()))
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{Event, Game, Observation, Perception, Player, PlayerId};
+
+impl Event {
+    /// The observations `perspective` would perceive going from `before` to `after`.
+    ///
+    /// Only facts visible in the states themselves are recovered: intel spending, waits and
+    /// anything else that leaves no trace are invisible to a diff. Deaths are attributed only
+    /// when exactly one other player stood close enough to have struck.
+    pub fn diff(before: &Game, after: &Game, perspective: PlayerId) -> Vec<Observation> {
+        let mut obs = vec![];
+        if before.alert != after.alert {
+            obs.push(Observation::Alert { level: after.alert });
+        }
+        for loc in after.cities.node_weights() {
+            let Some(old) = before.cities.node_weight(loc.index) else {
+                continue;
+            };
+            if let Some(by) = loc.control.filter(|&by| old.control != Some(by)) {
+                obs.push(Observation::Capture { by, at: loc.index });
+            }
+            if loc.control == Some(perspective) && loc.garrison != old.garrison {
+                obs.push(Observation::Garrison {
+                    at: loc.index,
+                    strength: loc.garrison,
+                });
+            }
+        }
+        let together = |g: &Game, p: &Player| {
+            g.players
+                .get(perspective)
+                .is_some_and(|me| me.location == p.location)
+        };
+        for (old, new) in before.players.iter().zip(&after.players) {
+            if new.id == perspective {
+                continue;
+            }
+            if new.resigned && !old.resigned {
+                obs.push(Observation::Resign { who: new.id });
+            }
+            let visible = !new.invisible && !new.concealed;
+            if visible && old.location != new.location {
+                obs.push(Observation::Move {
+                    who: new.id,
+                    from: Some(old.location),
+                    to: Some(new.location),
+                    how: Perception::Seen,
+                });
+            }
+            if !new.invisible && together(after, new) && !together(before, old) {
+                obs.push(Observation::Reveal {
+                    who: new.id,
+                    at: new.location,
+                });
+            }
+        }
+        for (old, new) in before.players.iter().zip(&after.players) {
+            if old.alive && !new.alive {
+                let mut suspects = after.players.iter().filter(|p| {
+                    p.id != new.id
+                        && p.alive
+                        && (p.location == new.location
+                            || after.cities.contains_edge(p.location, new.location))
+                });
+                if let (Some(by), None) = (suspects.next(), suspects.next()) {
+                    if perspective == by.id || perspective == new.id {
+                        obs.push(Observation::Death {
+                            by: by.id,
+                            of: new.id,
+                        });
+                    }
+                }
+            }
+        }
+        obs
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::ops::RangeBounds;
+
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+use vecmap::VecMap;
+
+use crate::{Action, ActionId, Envelope, PlayerId};
+
+/// Everything every player has observed over the whole game.
+///
+/// Unlike `Event`, this is never reset, so it is the omniscient record of the match.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct History {
+    pub observations: VecMap<PlayerId, Vec<Envelope>>,
+    /// Every action that resolved successfully, in order.
+    #[serde(default)]
+    pub actions: Vec<ActionRecord>,
+    /// Where each seat was placed, in seating order.
+    #[serde(default)]
+    pub spawns: Vec<NodeIndex>,
+    /// `Game::begin` was called, so players acted in turns.
+    #[serde(default)]
+    pub began: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionRecord {
+    pub id: ActionId,
+    pub turn: u32,
+    pub actor: PlayerId,
+    pub action: Action,
+}
+
+/// One step of a replay: the turn, who acted, what they did, and who observed what because of it.
+pub type Beat<'a> = (u32, PlayerId, &'a Action, Vec<(PlayerId, &'a Envelope)>);
+
+impl History {
+    /// Walk the match action by action, for replay viewers and transcripts.
+    ///
+    /// Start-of-turn observations are not caused by any action, and so don't appear here.
+    pub fn timeline(&self) -> impl Iterator<Item = Beat<'_>> {
+        let mut caused: BTreeMap<ActionId, Vec<(PlayerId, &Envelope)>> = BTreeMap::new();
+        for (&pid, envelopes) in &self.observations {
+            for envelope in envelopes {
+                if let Some(cause) = envelope.cause {
+                    caused.entry(cause).or_default().push((pid, envelope));
+                }
+            }
+        }
+        self.actions.iter().map(move |record| {
+            let observations = caused.remove(&record.id).unwrap_or_default();
+            (record.turn, record.actor, &record.action, observations)
+        })
+    }
+
+    pub fn record(&mut self, pid: PlayerId, envelope: Envelope) {
+        self.observations.entry(pid).or_default().push(envelope);
+    }
+
+    /// Coalesce every player's record; see `Event::coalesce`.
+    pub fn compact(&mut self) {
+        for (_, envelopes) in self.observations.iter_mut() {
+            crate::coalesce(envelopes);
+        }
+    }
+
+    /// Everything `pid` has observed, in the order they observed it.
+    pub fn received(&self, pid: PlayerId) -> &[Envelope] {
+        self.observations.get(&pid).map_or(&[], Vec::as_slice)
+    }
+
+    /// What `pid` has observed from sequence number `seq` onward, e.g. to catch a client up.
+    pub fn since(&self, pid: PlayerId, seq: u64) -> &[Envelope] {
+        let received = self.received(pid);
+        &received[received.partition_point(|e| e.seq < seq)..]
+    }
+
+    /// What `pid` has observed about `subject`.
+    pub fn about(&self, pid: PlayerId, subject: PlayerId) -> impl Iterator<Item = &Envelope> {
+        self.received(pid)
+            .iter()
+            .filter(move |e| e.obs.players().contains(&subject))
+    }
+
+    /// What `pid` has observed happening at `node`.
+    pub fn at(&self, pid: PlayerId, node: NodeIndex) -> impl Iterator<Item = &Envelope> {
+        self.received(pid)
+            .iter()
+            .filter(move |e| e.obs.locations().contains(&node))
+    }
+
+    /// Public observations `pid` received during `turns`.
+    pub fn public_in(
+        &self,
+        pid: PlayerId,
+        turns: impl RangeBounds<u32>,
+    ) -> impl Iterator<Item = &Envelope> {
+        self.received(pid)
+            .iter()
+            .filter(move |e| e.public && turns.contains(&e.turn))
+    }
+
+    /// Private observations `pid` received during `turns`.
+    pub fn private_in(
+        &self,
+        pid: PlayerId,
+        turns: impl RangeBounds<u32>,
+    ) -> impl Iterator<Item = &Envelope> {
+        self.received(pid)
+            .iter()
+            .filter(move |e| !e.public && turns.contains(&e.turn))
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::BTreeSet;
+
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+use vecmap::VecMap;
+
+use crate::{Envelope, Observation, PlayerId};
+
+/// A belief, and the turn it was last confirmed on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Known<T> {
+    pub value: T,
+    pub turn: u32,
+}
+
+impl<T> Known<T> {
+    /// Turns since the belief was last confirmed.
+    pub fn age(&self, now: u32) -> u32 {
+        now.saturating_sub(self.turn)
+    }
+}
+
+/// What one player currently believes about the board.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Knowledge {
+    /// Where each opponent was last known to be.
+    pub last_seen: VecMap<PlayerId, Known<NodeIndex>>,
+    /// Where each opponent was last heard leaving, when where they went is unknown.
+    #[serde(default)]
+    pub departed: VecMap<PlayerId, Known<NodeIndex>>,
+    /// Locations the player has stood on or had reported to them.
+    pub seen_locations: BTreeSet<NodeIndex>,
+    /// Who the player believes controls each location they know about.
+    pub control: VecMap<NodeIndex, Known<Option<PlayerId>>>,
+}
+
+impl Knowledge {
+    /// Update beliefs from a delivered observation.
+    pub fn learn(&mut self, envelope: &Envelope) {
+        let turn = envelope.turn;
+        match envelope.obs {
+            Observation::Reveal { who, at } => self.sighted(who, at, turn),
+            Observation::Sighting { who, at, as_of } => self.sighted(who, at, as_of),
+            Observation::Strike {
+                by: Some(by),
+                at: Some(at),
+                ..
+            } => self.sighted(by, at, turn),
+            Observation::Move { who, from, to, .. } => match (from, to) {
+                (_, Some(to)) => self.sighted(who, to, turn),
+                (Some(from), None) => self.left(who, from, turn),
+                (None, None) => {}
+            },
+            Observation::Capture { by, at } => self.controlled(at, Some(by), turn),
+            _ => {}
+        }
+    }
+
+    /// Forget beliefs more than `max_age` turns old.
+    pub fn expire(&mut self, now: u32, max_age: u32) {
+        self.last_seen.retain(|_, k| k.age(now) <= max_age);
+        self.departed.retain(|_, k| k.age(now) <= max_age);
+        self.control.retain(|_, k| k.age(now) <= max_age);
+    }
+
+    /// The player stood on `at` and saw who controls it.
+    pub fn visit(&mut self, at: NodeIndex, control: Option<PlayerId>, turn: u32) {
+        self.controlled(at, control, turn);
+    }
+
+    fn sighted(&mut self, who: PlayerId, at: NodeIndex, turn: u32) {
+        self.seen_locations.insert(at);
+        // An old informant report must not overwrite a fresher sighting.
+        if self.last_seen.get(&who).is_some_and(|k| k.turn > turn) {
+            return;
+        }
+        self.last_seen.insert(who, Known { value: at, turn });
+        self.departed.remove(&who);
+    }
+
+    /// `who` was heard leaving `from` for somewhere unknown.
+    fn left(&mut self, who: PlayerId, from: NodeIndex, turn: u32) {
+        self.seen_locations.insert(from);
+        if self.last_seen.get(&who).is_some_and(|k| k.turn > turn) {
+            return;
+        }
+        self.last_seen.remove(&who);
+        self.departed.insert(who, Known { value: from, turn });
+    }
+
+    fn controlled(&mut self, at: NodeIndex, control: Option<PlayerId>, turn: u32) {
+        self.seen_locations.insert(at);
+        self.control.insert(
+            at,
+            Known {
+                value: control,
+                turn,
+            },
+        );
+    }
+}
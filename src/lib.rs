@@ -2,19 +2,59 @@ use petgraph::{
     graph::{NodeIndex, UnGraph},
     visit::EdgeRef,
 };
+use rand::{seq::IteratorRandom, Rng, SeedableRng};
+use rand_pcg::Pcg64;
 use serde::{Deserialize, Serialize};
 use vecmap::{VecMap};
 
+mod bot;
+mod describe;
+mod diff;
+mod history;
+mod knowledge;
+mod map;
+pub mod protocol;
+mod replay;
+mod ruleset;
+#[cfg(feature = "seal")]
+pub mod seal;
+mod sink;
+mod svg;
+mod view;
+pub mod wire;
+
+pub use bot::BotLevel;
+pub use describe::{Description, Param};
+pub use history::{ActionRecord, Beat, History};
+pub use knowledge::{Knowledge, Known};
+pub use map::{Map, Site};
+pub use replay::{Replay, REPLAY_VERSION};
+pub use ruleset::Ruleset;
+pub use sink::{EventSink, Sinks};
+pub use view::{GameView, LocationView, OpponentView};
+
 pub type Intel = u32;
 pub type PlayerId = usize;
+/// Identifies one call to `Game::do_action`, linking it to the observations it caused.
+pub type ActionId = u64;
 
 const COLORS: &[&str] = &["red", "blue", "green", "yellow"];
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GameError {
     NotEnoughIntel,
     NotYourTurn,
     WouldNoop,
+    /// The location is held by a garrison that must be worn down first.
+    Garrisoned,
+    /// The action requires standing on a location you control.
+    NotControlled,
+    /// The acting or targeted player isn't seated in this game.
+    NoSuchPlayer,
+    /// The targeted location isn't on the map.
+    NoSuchLocation,
+    /// Moves only go along an edge from the current location.
+    NotAdjacent,
 }
 
 pub type GameResult = Result<(), GameError>;
@@ -31,6 +71,12 @@ pub struct Location {
     pub index: NodeIndex,
     /// Controling player, if any.
     pub control: Option<PlayerId>,
+    /// Defenders that must be struck down before control can flip.
+    #[serde(default)]
+    pub garrison: u32,
+    /// Layout position from the map, roughly in a unit square.
+    #[serde(default)]
+    pub pos: (f32, f32),
 }
 
 #[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
@@ -47,18 +93,30 @@ pub struct Player {
     pub concealed: bool,
     /// If invisible, concealment is ignored and the peg is never observed.
     pub invisible: bool,
+    /// A prepared strike also hits every location adjacent to the striker.
+    pub prepared: bool,
+    /// Conceded players take no further turns.
+    pub resigned: bool,
     /// Convenience, index in player array.
     pub id: PlayerId,
     /// Location of peg in game graph.
     pub location: NodeIndex,
 }
 
+/// How a player is presented to humans.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    /// A graphviz/CSS color name; falls back to the seat's default color.
+    pub color: Option<String>,
+}
+
 impl Player {
-    fn purchase(&mut self, which: IntelKind) -> GameResult {
-        if which.cost() > self.intel { 
+    fn purchase(&mut self, cost: Intel) -> GameResult {
+        if cost > self.intel { 
             return Err(GameError::NotEnoughIntel)
         }
-        self.intel = self.intel.saturating_sub(which.cost());
+        self.intel = self.intel.saturating_sub(cost);
         Ok(())
     }
 }
@@ -67,55 +125,479 @@ pub struct Game {
     pub cities: UnGraph<Location, ()>,
     pub players: Vec<Player>,
     pub event: Event,
+    #[serde(default)]
+    pub rules: Ruleset,
+    /// Global alert level, raised by loud actions and decaying while things are quiet.
+    #[serde(default)]
+    pub alert: u32,
+    /// Turns started since the alert level last changed.
+    #[serde(default)]
+    pub quiet_turns: u32,
+    /// Number of turns started so far.
+    #[serde(default)]
+    pub turn: u32,
+    #[serde(default)]
+    pub phase: Phase,
+    /// Sequence number of the next observation each player will receive.
+    #[serde(default)]
+    pub next_seq: VecMap<PlayerId, u64>,
+    /// Observations waiting for their turn to be delivered.
+    #[serde(default)]
+    pub delayed: Vec<Delayed>,
+    /// Seed the game's randomness was started from.
+    pub seed: u64,
+    pub rng: Pcg64,
+    #[serde(default)]
+    pub history: History,
+    /// What each player currently believes; see `Game::knowledge`.
+    #[serde(default)]
+    pub beliefs: VecMap<PlayerId, Knowledge>,
+    /// Id the next action will be given.
+    #[serde(default)]
+    pub next_action_id: ActionId,
+    /// The action currently being resolved, if any.
+    #[serde(default)]
+    pub acting: Option<ActionId>,
+    #[serde(skip)]
+    pub sinks: Sinks,
+    /// Display names and colors, by player.
+    #[serde(default)]
+    pub profiles: VecMap<PlayerId, Profile>,
+    /// Player whose turn it is. Once set, nobody else may act.
+    #[serde(default)]
+    pub active: Option<PlayerId>,
+    /// Actions the active player has left this turn.
+    #[serde(default)]
+    pub actions_left: u32,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Game {
     pub fn new() -> Game {
+        Game::with_rules(Ruleset::default())
+    }
+
+    pub fn with_rules(rules: Ruleset) -> Game {
+        Game::seeded(rules, rand::random())
+    }
+
+    /// A game whose every random outcome is determined by `seed`.
+    pub fn seeded(rules: Ruleset, seed: u64) -> Game {
         Game {
             cities: UnGraph::new_undirected(),
             players: vec![],
             event: Event::default(),
+            rules,
+            alert: 0,
+            quiet_turns: 0,
+            turn: 0,
+            phase: Phase::default(),
+            next_seq: VecMap::new(),
+            delayed: vec![],
+            seed,
+            rng: Pcg64::seed_from_u64(seed),
+            history: History::default(),
+            beliefs: VecMap::new(),
+            next_action_id: 0,
+            acting: None,
+            sinks: Sinks::default(),
+            profiles: VecMap::new(),
+            active: None,
+            actions_left: 0,
         }
     }
 
+    /// A game played on `map`.
+    pub fn on_map(map: &Map, rules: Ruleset) -> Game {
+        let mut game = Game::with_rules(rules);
+        game.cities = map.graph();
+        game
+    }
+
+    /// Have `sink` called as observations are produced, from now on.
+    pub fn subscribe(&mut self, sink: impl EventSink + Send + 'static) {
+        self.sinks.0.push(Box::new(sink));
+    }
+
     pub fn reset_event(&mut self) {
         self.event = Event::default();
     }
 
-    pub fn do_action(&mut self, pid: PlayerId, action: Action) -> GameResult {
+    /// Resolve an action, returning the id stamped on every observation it causes.
+    pub fn do_action(&mut self, pid: PlayerId, action: Action) -> Result<ActionId, GameError> {
+        if self.active.is_some_and(|active| active != pid) {
+            return Err(GameError::NotYourTurn)
+        }
+        let id = self.next_action_id;
+        self.next_action_id += 1;
+        self.acting = Some(id);
+        let result = self.resolve(pid, action.clone());
+        self.acting = None;
+        result?;
+        let passed = matches!(action, Action::Pass);
+        self.history.actions.push(ActionRecord {
+            id,
+            turn: self.turn,
+            actor: pid,
+            action,
+        });
+        if self.active == Some(pid) {
+            self.actions_left = if passed { 0 } else { self.actions_left.saturating_sub(1) };
+            if self.actions_left == 0 {
+                self.end_turn();
+            }
+        }
+        Ok(id)
+    }
+
+    /// Check that an action refers only to things that exist, without resolving it.
+    pub fn validate(&self, pid: PlayerId, action: &Action) -> GameResult {
+        if pid >= self.players.len() {
+            return Err(GameError::NoSuchPlayer)
+        }
+        match *action {
+            Action::Move(to) => {
+                if self.cities.node_weight(to).is_none() {
+                    return Err(GameError::NoSuchLocation)
+                }
+                if self.cities.find_edge(self.players[pid].location, to).is_none() {
+                    return Err(GameError::NotAdjacent)
+                }
+            }
+            Action::Reveal(other) | Action::Informant(other) if other >= self.players.len() => {
+                return Err(GameError::NoSuchPlayer)
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn resolve(&mut self, pid: PlayerId, action: Action) -> GameResult {
+        self.validate(pid, &action)?;
         match action {
             Action::Strike => self.strike(pid),
             Action::Wait => self.wait(pid),
-            Action::Capture => self.capture(pid),
+            Action::Capture => self.capture(pid)?,
             Action::HideSignals => self.hide_signals(pid)?,
             Action::Invisible => self.invisible_action(pid)?,
-            Action::Prepare => self.prepare(pid),
+            Action::Prepare => self.prepare(pid)?,
+            Action::Reinforce => self.reinforce(pid)?,
             Action::Move(to) => { self.try_move(pid, to); },
             Action::Reveal(other) => self.reveal_action(pid, Some(other))?,
+            Action::Informant(other) => self.informant(pid, other)?,
+            Action::Pass => {}
         }
         Ok(())
     }
 
+    /// Seat a new player at `at`, optionally with a display profile.
+    pub fn spawn_player(&mut self, at: NodeIndex, profile: Option<Profile>) -> PlayerId {
+        let pid = self.players.len();
+        self.players.push(Player {
+            alive: true,
+            id: pid,
+            location: at,
+            ..Default::default()
+        });
+        self.history.spawns.push(at);
+        if let Some(profile) = profile {
+            self.profiles.insert(pid, profile);
+        }
+        self.survey(pid);
+        pid
+    }
+
+    /// The player's display name, or "player N" if they have none.
+    pub fn name(&self, pid: PlayerId) -> String {
+        match self.profiles.get(&pid) {
+            Some(profile) => profile.name.clone(),
+            None => format!("player {pid}"),
+        }
+    }
+
+    /// The player's display color.
+    pub fn color(&self, pid: PlayerId) -> &str {
+        self.profiles
+            .get(&pid)
+            .and_then(|p| p.color.as_deref())
+            .unwrap_or(COLORS[pid % COLORS.len()])
+    }
+
+    /// An English account of `obs` using players' display names.
+    pub fn describe(&self, obs: &Observation) -> String {
+        obs.describe().english(|pid| self.name(pid))
+    }
+
+    /// What `pid` currently believes about the board: last known positions and control.
+    pub fn knowledge(&self, pid: PlayerId) -> Knowledge {
+        self.beliefs.get(&pid).cloned().unwrap_or_default()
+    }
+
+    /// `pid` looks around the location they are standing on.
+    fn survey(&mut self, pid: PlayerId) {
+        let at = self.players[pid].location;
+        let control = self.cities.node_weight(at).and_then(|loc| loc.control);
+        self.beliefs.entry(pid).or_default().visit(at, control, self.turn);
+    }
+
+    /// Players still taking turns, in seating order.
+    pub fn turn_order(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.players
+            .iter()
+            .filter(|p| p.alive && !p.resigned)
+            .map(|p| p.id)
+    }
+
+    /// The last player still taking turns, once everyone else is dead or has resigned.
+    pub fn winner(&self) -> Option<PlayerId> {
+        if self.players.len() < 2 {
+            return None
+        }
+        let mut left = self.turn_order();
+        match (left.next(), left.next()) {
+            (Some(pid), None) => Some(pid),
+            _ => None,
+        }
+    }
+
+    /// Whose turn follows `pid`'s, if anyone is left to take it.
+    pub fn next_player(&self, pid: PlayerId) -> Option<PlayerId> {
+        self.turn_order()
+            .find(|&p| p > pid)
+            .or_else(|| self.turn_order().next())
+    }
+
+    /// Start the first turn, after which players act in turn order.
+    pub fn begin(&mut self) -> GameResult {
+        if self.active.is_some() {
+            return Err(GameError::WouldNoop)
+        }
+        let first = self.turn_order().next().ok_or(GameError::NoSuchPlayer)?;
+        self.history.began = true;
+        self.start_turn(first);
+        Ok(())
+    }
+
+    /// Hand the turn to the next player in turn order, or stop if nobody is left.
+    pub fn end_turn(&mut self) {
+        let Some(pid) = self.active else { return };
+        match self.next_player(pid) {
+            Some(next) => self.start_turn(next),
+            None => {
+                self.active = None;
+                self.actions_left = 0;
+            }
+        }
+    }
+
+    /// The active player ran out of time: say so and pass the rest of their turn.
+    pub fn time_out(&mut self) -> GameResult {
+        let pid = self.active.ok_or(GameError::WouldNoop)?;
+        self.broadcast(Observation::TimedOut { who: pid });
+        self.do_action(pid, Action::Pass)?;
+        Ok(())
+    }
+
+    /// Concede the game, leaving the turn order and (per the ruleset) releasing held territory.
+    pub fn resign(&mut self, pid: PlayerId) -> GameResult {
+        if pid >= self.players.len() {
+            return Err(GameError::NoSuchPlayer)
+        }
+        if self.players[pid].resigned {
+            return Err(GameError::WouldNoop)
+        }
+        self.players[pid].resigned = true;
+        if self.rules.resign_releases_territory {
+            for loc in self.cities.node_weights_mut() {
+                if loc.control == Some(pid) {
+                    loc.control = None;
+                    loc.garrison = 0;
+                }
+            }
+        }
+        self.broadcast(Observation::Resign { who: pid });
+        if self.active == Some(pid) {
+            self.end_turn();
+        }
+        Ok(())
+    }
+
+    /// What an intel purchase costs at the current alert level.
+    pub fn cost(&self, kind: IntelKind) -> Intel {
+        match kind {
+            IntelKind::Reveal if self.alert >= self.rules.alert_reveal_threshold => {
+                kind.cost().saturating_sub(1)
+            }
+            _ => kind.cost(),
+        }
+    }
+
+    fn purchase(&mut self, pid: PlayerId, kind: IntelKind) -> GameResult {
+        let cost = self.cost(kind);
+        self.players[pid].purchase(cost)
+    }
+
+    /// Raise the alert level after a loud action, broadcasting the new level.
+    fn raise_alert(&mut self, by: u32) {
+        let level = (self.alert + by).min(self.rules.max_alert);
+        self.quiet_turns = 0;
+        if level != self.alert {
+            self.alert = level;
+            self.broadcast(Observation::Alert { level });
+        }
+    }
+
+    /// Let the alert level settle after enough quiet turns.
+    fn decay_alert(&mut self) {
+        self.quiet_turns += 1;
+        if self.alert > 0 && self.quiet_turns >= self.rules.alert_decay_turns {
+            self.alert -= 1;
+            self.quiet_turns = 0;
+            self.broadcast(Observation::Alert { level: self.alert });
+        }
+    }
+
+    /// Stamp an observation with the current turn and the recipient's next sequence number.
+    fn envelope(&mut self, pid: PlayerId, public: bool, obs: Observation) -> Envelope {
+        let seq = self.next_seq.entry(pid).or_default();
+        let envelope = Envelope {
+            turn: self.turn,
+            phase: self.phase,
+            seq: *seq,
+            public,
+            obs,
+            count: 1,
+            cause: self.acting,
+        };
+        *seq += 1;
+        envelope
+    }
+
+    /// Per the ruleset's noise, blur a location report into one of two candidates.
+    fn fuzz(&mut self, obs: Observation) -> Observation {
+        let (who, at) = match obs {
+            Observation::Reveal { who, at } | Observation::Sighting { who, at, .. } => (who, at),
+            _ => return obs,
+        };
+        if !self.rng.gen_bool(self.rules.noise) {
+            return obs;
+        }
+        let Some(decoy) = self.cities.neighbors(at).choose(&mut self.rng) else {
+            return obs;
+        };
+        let at = if self.rng.gen() { [at, decoy] } else { [decoy, at] };
+        Observation::Rumor { who, at }
+    }
+
+    /// Hand an envelope to its recipient, keeping it in the history too.
+    fn deliver(&mut self, pid: PlayerId, envelope: Envelope) {
+        self.sinks.envelope(pid, &envelope);
+        self.beliefs.entry(pid).or_default().learn(&envelope);
+        self.history.record(pid, envelope.clone());
+        self.event.note(pid, envelope);
+    }
+
     /// A private note for a player to know.
     fn note(&mut self, pid: PlayerId, obs: Observation) {
-        self.event.note(pid, obs)
+        let obs = self.fuzz(obs);
+        self.sinks.observation(Some(pid), &obs);
+        let envelope = self.envelope(pid, false, obs);
+        self.deliver(pid, envelope)
     }
 
     /// Public information for everyone to learn.
     fn broadcast(&mut self, obs: Observation) {
+        self.sinks.observation(None, &obs);
+        for pid in 0..self.players.len() {
+            let envelope = self.envelope(pid, true, obs.clone());
+            self.deliver(pid, envelope);
+        }
         self.event.broadcast(obs)
     }
 
+    /// Players standing within `radius` steps of `at`.
+    pub fn players_near(&self, at: NodeIndex, radius: u32) -> Vec<PlayerId> {
+        let distances = petgraph::algo::dijkstra(&self.cities, at, None, |_| 1u32);
+        self.players
+            .iter()
+            .filter(|p| distances.get(&p.location).is_some_and(|&d| d <= radius))
+            .map(|p| p.id)
+            .collect()
+    }
+
+    /// Local information, learned only by players within `radius` steps of `at`.
+    pub fn broadcast_near(&mut self, at: NodeIndex, radius: u32, obs: Observation) {
+        for pid in self.players_near(at, radius) {
+            self.note(pid, obs.clone());
+        }
+    }
+
+    /// Route a scenario- or mod-defined event to `to`, or to everyone if `None`.
+    pub fn emit_custom(
+        &mut self,
+        to: Option<PlayerId>,
+        key: impl Into<String>,
+        data: serde_json::Value,
+    ) {
+        let obs = Observation::Custom {
+            key: key.into(),
+            data,
+        };
+        match to {
+            Some(pid) => self.note(pid, obs),
+            None => self.broadcast(obs),
+        }
+    }
+
+    /// Deliver `obs` to `to` (or everyone, if `None`) once `delay` more turns have started.
+    pub fn schedule(&mut self, to: Option<PlayerId>, delay: u32, obs: Observation) {
+        self.delayed.push(Delayed {
+            due: self.turn + delay,
+            to,
+            obs,
+            cause: self.acting,
+        });
+    }
+
+    /// Deliver every delayed observation that has come due.
+    fn flush_delayed(&mut self) {
+        let (due, pending) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition::<Vec<_>, _>(|d| d.due <= self.turn);
+        self.delayed = pending;
+        for d in due {
+            self.acting = d.cause;
+            match d.to {
+                Some(pid) => self.note(pid, d.obs),
+                None => self.broadcast(d.obs),
+            }
+        }
+        self.acting = None;
+    }
+
     /// Attempt a move, returning true if the move completed.
     pub fn try_move(&mut self, pid: PlayerId, to: NodeIndex) -> bool {
-        if self
-            .cities
-            .find_edge(self.players[pid].location, to)
-            .is_none()
-        {
+        let from = self.players[pid].location;
+        if self.cities.find_edge(from, to).is_none() {
             return false;
         }
         self.players[pid].location = to;
+        self.survey(pid);
+        let mover = self.players[pid];
+        let sightings = self
+            .players
+            .iter()
+            .filter(|pl| pl.id != pid)
+            .filter_map(|pl| Some((pl.id, self.perceive_move(pl, &mover, from)?)))
+            .collect::<Vec<_>>();
+        for (observer, obs) in sightings {
+            self.note(observer, obs);
+        }
         let mut obs = vec!();
         if self.players[pid].active_scan {
             for pl in &self.players {
@@ -130,8 +612,50 @@ impl Game {
         true
     }
 
+    /// How (if at all) `observer` perceives `mover` having just stepped over from `from`.
+    fn perceive_move(
+        &self,
+        observer: &Player,
+        mover: &Player,
+        from: NodeIndex,
+    ) -> Option<Observation> {
+        let to = mover.location;
+        let sighting = |from, to, how| Observation::Move {
+            who: mover.id,
+            from,
+            to,
+            how,
+        };
+        if mover.invisible {
+            return None;
+        }
+        if !mover.concealed {
+            return Some(sighting(Some(from), Some(to), Perception::Seen));
+        }
+        let near_to = observer.location == to || self.cities.contains_edge(observer.location, to);
+        if observer.active_scan && near_to {
+            return Some(sighting(Some(from), Some(to), Perception::Scanned));
+        }
+        let heard_from = (observer.location == from).then_some(from);
+        let heard_to = (observer.location == to).then_some(to);
+        if heard_from.is_some() || heard_to.is_some() {
+            return Some(sighting(heard_from, heard_to, Perception::Heard));
+        }
+        None
+    }
+
     /// Collect intel and reveal anyone on the current node.
     pub fn start_turn(&mut self, pid: PlayerId) {
+        self.turn += 1;
+        self.phase = Phase::Upkeep;
+        self.decay_alert();
+        self.flush_delayed();
+        if let Some(max_age) = self.rules.intel_expiry {
+            for (_, knowledge) in self.beliefs.iter_mut() {
+                knowledge.expire(self.turn, max_age);
+            }
+        }
+        self.survey(pid);
         let cur_city = self
             .cities
             .node_weight(self.players[pid].location)
@@ -148,35 +672,49 @@ impl Game {
             })
             .sum::<u32>()
             + cur_city.pending_powerup.unwrap_or(0);
+        let mut reveals = vec![];
         for p in &mut self.players {
             if p.id != pid && !p.invisible && cur_city.index == p.location {
                 p.concealed = false; // TODO: N-player, make this a set?
-                self.event.note(pid, Observation::Reveal { who: p.id, at: p.location })
+                reveals.push(Observation::Reveal { who: p.id, at: p.location });
             }
             if p.id == pid {
                 p.intel += intel_income;
                 p.invisible = false; // invisibility expires, sadly!
             }
         }
+        let actions = self.rules.actions_per_turn + cur_city.boost as u32;
+        for obs in reveals {
+            self.note(pid, obs);
+        }
+        self.active = Some(pid);
+        self.actions_left = actions;
+        self.broadcast(Observation::TurnStart { who: pid, actions });
+        self.phase = Phase::Action;
     }
 
-    pub fn render(&self, perspective: PlayerId) -> String {
+    pub fn render(&self, _perspective: PlayerId) -> String {
         // TODO: use `perspective` to conceal other players.
         let mut d = vec![String::from("graph {")];
 
         for location in self.cities.node_weights() {
             let size = location.base_income as f32 * 0.25;
             let color = match location.control {
-                Some(idx) => COLORS[idx],
+                Some(idx) => self.color(idx),
                 None => "white",
             };
+            let tooltip = location
+                .control
+                .map(|idx| self.name(idx).replace('"', "\\\""))
+                .unwrap_or_default();
             let pending_powerup = location
                 .pending_powerup
                 .map(|x| x.to_string())
                 .unwrap_or(String::new());
             let boost = if location.boost { "⚡" } else { "" };
+            let garrison = "🛡".repeat(location.garrison as usize);
             d.push(format!(
-                "{} [ size={size} style=filled fillcolor={color} label={pending_powerup}{boost} ]",
+                "{} [ size={size} style=filled fillcolor=\"{color}\" tooltip=\"{tooltip}\" label=\"{pending_powerup}{boost}{garrison}\" ]",
                 location.index.index()
             ))
         }
@@ -208,20 +746,44 @@ impl Game {
     }
 
     pub fn strike(&mut self, pid: PlayerId) {
+        self.raise_alert(self.rules.alert_per_strike);
+        let origin = self.players[pid].location;
+        let prepared = std::mem::take(&mut self.players[pid].prepared);
+        let mut targets = vec![origin];
+        if prepared {
+            targets.extend(self.cities.neighbors(origin));
+        }
+        for &at in &targets {
+            let loc = &mut self.cities[at];
+            match loc.control {
+                Some(owner) if owner != pid && loc.garrison > 0 => {
+                    loc.garrison -= 1;
+                    let strength = loc.garrison;
+                    self.note(owner, Observation::Garrison { at, strength });
+                }
+                _ => {}
+            }
+        }
+        let earshot = self.players_near(origin, self.rules.strike_noise_radius);
         for pl in 0..self.players.len() {
             if pl != pid {
-                if self.players[pid].location == self.players[pl].location {
+                if targets.contains(&self.players[pl].location) {
                     self.players[pl].alive = false;
                     let ded = Observation::Death { by: pid, of: pl };
-                    self.note(pid, ded);
+                    self.note(pid, ded.clone());
                     self.note(pl, ded);
                 }
-                if self.players[pl].visible_violence || !self.players[pl].alive {
+                if self.players[pl].visible_violence
+                    || !self.players[pl].alive
+                    || earshot.contains(&pl)
+                    || self.alert >= self.rules.alert_violence_threshold
+                {
                     self.note(
                         pl,
                         Observation::Strike {
                             by: Some(pid),
-                            at: Some(self.players[pid].location),
+                            at: Some(origin),
+                            prepared,
                         },
                     );
                 } else {
@@ -230,6 +792,7 @@ impl Game {
                         Observation::Strike {
                             by: Some(pid),
                             at: None,
+                            prepared,
                         },
                     );
                 }
@@ -237,22 +800,59 @@ impl Game {
         }
     }
 
+    /// Lie low: collect a trickle of intel and recover concealment, per the ruleset.
     pub fn wait(&mut self, pid: PlayerId)  {
-        self.broadcast(Observation::WaitMove { by: Some(pid) });
+        let player = &mut self.players[pid];
+        player.intel += self.rules.wait_income;
+        if self.rules.wait_conceals {
+            player.concealed = true;
+        }
+        // Others learn that someone waited, not who.
+        for pl in 0..self.players.len() {
+            let by = (pl == pid).then_some(pid);
+            self.note(pl, Observation::WaitMove { by });
+        }
     }
 
     /// Try to capture the location for yourself.
-    pub fn capture(&mut self, pid: PlayerId) {
-        self.cities
+    pub fn capture(&mut self, pid: PlayerId) -> GameResult {
+        let loc = self
+            .cities
             .node_weight_mut(self.players[pid].location)
-            .unwrap()
-            .control = Some(pid);
+            .unwrap();
+        if loc.control.is_some_and(|owner| owner != pid) && loc.garrison > 0 {
+            return Err(GameError::Garrisoned)
+        }
+        if loc.control != Some(pid) {
+            loc.garrison = 0;
+        }
+        loc.control = Some(pid);
         self.broadcast(
             Observation::Capture {
                 by: pid,
                 at: self.players[pid].location,
             }
         );
+        self.raise_alert(self.rules.alert_per_capture);
+        Ok(())
+    }
+
+    /// Spend intel to add a defender to the garrison of a location you control and stand on.
+    pub fn reinforce(&mut self, pid: PlayerId) -> GameResult {
+        let at = self.players[pid].location;
+        let loc = &self.cities[at];
+        if loc.control != Some(pid) {
+            return Err(GameError::NotControlled)
+        }
+        if loc.garrison >= self.rules.max_garrison {
+            return Err(GameError::WouldNoop)
+        }
+        self.purchase(pid, IntelKind::Reinforce)?;
+        self.intel_reveal(pid, IntelKind::Reinforce);
+        self.cities[at].garrison += 1;
+        let strength = self.cities[at].garrison;
+        self.note(pid, Observation::Garrison { at, strength });
+        Ok(())
     }
 
     /// Hide your intel emissions.
@@ -260,7 +860,7 @@ impl Game {
         if self.players[pid].hidden_signals {
             return Err(GameError::WouldNoop)
         }
-        self.players[pid].purchase(IntelKind::HideSignals)?;
+        self.purchase(pid, IntelKind::HideSignals)?;
         self.intel_reveal(pid, IntelKind::HideSignals);
         self.players[pid].hidden_signals = true;
         Ok(())
@@ -271,7 +871,7 @@ impl Game {
         if self.players[pid].invisible {
             return Err(GameError::WouldNoop)
         }
-        self.players[pid].purchase(IntelKind::Invisible)?;
+        self.purchase(pid, IntelKind::Invisible)?;
         self.intel_reveal(pid, IntelKind::Invisible);
         self.players[pid].invisible = true;
         Ok(())
@@ -284,7 +884,7 @@ impl Game {
         pid: PlayerId,
         reveal: Option<PlayerId>,
     ) -> GameResult {
-        self.players[pid].purchase(IntelKind::Reveal)?;
+        self.purchase(pid, IntelKind::Reveal)?;
         if let Some(reveal) = reveal {
             if !self.players[reveal].invisible {
                 self.note( pid,
@@ -321,12 +921,34 @@ impl Game {
         Ok(())
     }
 
-    pub fn prepare(&mut self, pid: PlayerId) {
+    /// Pay an informant, who reports where a player is now -- but only some turns later.
+    ///
+    /// Unlike a reveal, informants see through invisibility.
+    pub fn informant(&mut self, pid: PlayerId, target: PlayerId) -> GameResult {
+        self.purchase(pid, IntelKind::Informant)?;
+        self.intel_reveal(pid, IntelKind::Informant);
+        let sighting = Observation::Sighting {
+            who: target,
+            at: self.players[target].location,
+            as_of: self.turn,
+        };
+        self.schedule(Some(pid), self.rules.informant_delay, sighting);
+        Ok(())
+    }
+
+    /// Bank a prepared strike, widening the next strike to neighbouring locations.
+    pub fn prepare(&mut self, pid: PlayerId) -> GameResult {
+        if self.players[pid].prepared {
+            return Err(GameError::WouldNoop)
+        }
+        self.purchase(pid, IntelKind::Prepare)?;
         self.intel_reveal(pid, IntelKind::Prepare);
+        self.players[pid].prepared = true;
+        Ok(())
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 /// Subjective information about changes to the game state.
 pub enum Observation {
     Death {
@@ -336,6 +958,8 @@ pub enum Observation {
     Strike {
         by: Option<PlayerId>,
         at: Option<NodeIndex>,
+        /// The strike was prepared, and also hit adjacent locations.
+        prepared: bool,
     },
     WaitMove {
         by: Option<PlayerId>,
@@ -355,6 +979,211 @@ pub enum Observation {
     RevealFailure {
         who: PlayerId,
     },
+    /// The garrison at a location you control changed strength.
+    Garrison {
+        at: NodeIndex,
+        strength: u32,
+    },
+    /// The global alert level changed.
+    Alert {
+        level: u32,
+    },
+    /// A player conceded the game.
+    Resign {
+        who: PlayerId,
+    },
+    /// A report that a player was at a location as of an earlier turn.
+    Sighting {
+        who: PlayerId,
+        at: NodeIndex,
+        as_of: u32,
+    },
+    /// An unconfirmed report placing a player at one of two locations.
+    Rumor {
+        who: PlayerId,
+        at: [NodeIndex; 2],
+    },
+    /// A peg moved, with whichever endpoints the observer perceived.
+    Move {
+        who: PlayerId,
+        from: Option<NodeIndex>,
+        to: Option<NodeIndex>,
+        how: Perception,
+    },
+    /// A player's turn began with this many actions.
+    TurnStart {
+        who: PlayerId,
+        actions: u32,
+    },
+    /// A player's turn was skipped because they took too long.
+    TimedOut {
+        who: PlayerId,
+    },
+    /// An event defined by a scenario or mod rather than the core rules.
+    Custom {
+        key: String,
+        data: serde_json::Value,
+    },
+}
+
+impl Observation {
+    /// Broad class of the observation, for grouping and filtering logs.
+    pub fn category(&self) -> Category {
+        match self {
+            Observation::Death { .. } | Observation::Strike { .. } | Observation::Garrison { .. } => {
+                Category::Combat
+            }
+            Observation::Intel { .. }
+            | Observation::Reveal { .. }
+            | Observation::RevealFailure { .. }
+            | Observation::Sighting { .. }
+            | Observation::Rumor { .. } => Category::Intel,
+            Observation::WaitMove { .. } | Observation::Move { .. } => Category::Movement,
+            Observation::Capture { .. } => Category::Economy,
+            Observation::Alert { .. }
+            | Observation::Resign { .. }
+            | Observation::TurnStart { .. }
+            | Observation::TimedOut { .. }
+            | Observation::Custom { .. } => Category::System,
+        }
+    }
+
+    /// Players the observation is about.
+    pub fn players(&self) -> Vec<PlayerId> {
+        match *self {
+            Observation::Death { by, of } => vec![by, of],
+            Observation::Strike { by, .. }
+            | Observation::WaitMove { by }
+            | Observation::Intel { by, .. } => by.into_iter().collect(),
+            Observation::Capture { by, .. } => vec![by],
+            Observation::Reveal { who, .. }
+            | Observation::RevealFailure { who }
+            | Observation::Resign { who }
+            | Observation::TurnStart { who, .. }
+            | Observation::TimedOut { who }
+            | Observation::Sighting { who, .. }
+            | Observation::Rumor { who, .. }
+            | Observation::Move { who, .. } => vec![who],
+            Observation::Garrison { .. }
+            | Observation::Alert { .. }
+            | Observation::Custom { .. } => vec![],
+        }
+    }
+
+    /// Locations the observation places something at.
+    pub fn locations(&self) -> Vec<NodeIndex> {
+        match *self {
+            Observation::Strike { at, .. } => at.into_iter().collect(),
+            Observation::Capture { at, .. }
+            | Observation::Reveal { at, .. }
+            | Observation::Garrison { at, .. }
+            | Observation::Sighting { at, .. } => vec![at],
+            Observation::Rumor { at, .. } => at.to_vec(),
+            Observation::Move { from, to, .. } => from.into_iter().chain(to).collect(),
+            Observation::Death { .. }
+            | Observation::WaitMove { .. }
+            | Observation::Intel { .. }
+            | Observation::RevealFailure { .. }
+            | Observation::Alert { .. }
+            | Observation::Resign { .. }
+            | Observation::TurnStart { .. }
+            | Observation::TimedOut { .. }
+            | Observation::Custom { .. } => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Broad classes of observation.
+pub enum Category {
+    /// Strikes, deaths and garrisons.
+    Combat,
+    /// Intel spending and reports on where players are.
+    Intel,
+    Movement,
+    /// Territory changing hands.
+    Economy,
+    /// Game-wide state such as the alert level and resignations.
+    System,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// How an observation of another player came about.
+pub enum Perception {
+    /// The peg was in plain sight.
+    Seen,
+    /// A concealed peg was overheard by someone standing at one end of its move.
+    Heard,
+    /// A concealed peg was picked up by an active scan nearby.
+    Scanned,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Where in a turn an observation was produced.
+pub enum Phase {
+    /// Before the first turn has started.
+    #[default]
+    Setup,
+    /// Start-of-turn income and reveals.
+    Upkeep,
+    /// The active player's actions.
+    Action,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// An observation as delivered to one player, with ordering metadata.
+pub struct Envelope {
+    pub turn: u32,
+    pub phase: Phase,
+    /// Position in the recipient's stream; consecutive from zero unless coalesced.
+    pub seq: u64,
+    /// Whether every player was sent this observation.
+    pub public: bool,
+    pub obs: Observation,
+    /// How many times the observation was made, once repeats are coalesced.
+    #[serde(default = "Envelope::once")]
+    pub count: u32,
+    /// The action that caused the observation; `None` for start-of-turn upkeep.
+    #[serde(default)]
+    pub cause: Option<ActionId>,
+}
+
+impl Envelope {
+    fn once() -> u32 {
+        1
+    }
+}
+
+/// Merge repeats of the same observation within a turn into their first occurrence.
+pub(crate) fn coalesce(envelopes: &mut Vec<Envelope>) {
+    let mut merged: Vec<Envelope> = Vec::with_capacity(envelopes.len());
+    let mut turn_start = 0;
+    for envelope in envelopes.drain(..) {
+        if merged.last().is_some_and(|last| last.turn != envelope.turn) {
+            turn_start = merged.len();
+        }
+        match merged[turn_start..]
+            .iter_mut()
+            .find(|m| m.obs == envelope.obs && m.public == envelope.public)
+        {
+            Some(m) => m.count += envelope.count,
+            None => merged.push(envelope),
+        }
+    }
+    *envelopes = merged;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// An observation held back until a later turn.
+pub struct Delayed {
+    /// Turn at whose start the observation is delivered.
+    pub due: u32,
+    /// Recipient, or everyone if `None`.
+    pub to: Option<PlayerId>,
+    pub obs: Observation,
+    /// The action that scheduled the observation.
+    #[serde(default)]
+    pub cause: Option<ActionId>,
 }
 
 /// An Event records the observations that occur between successive game states.
@@ -363,26 +1192,36 @@ pub enum Observation {
 /// without sending information that would let them cheat (hopefully!)
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Event {
-    pub private_observations: VecMap<PlayerId, Vec<Observation>>,
+    /// Everything each player observed, public and private, in the order they observed it.
+    pub observations: VecMap<PlayerId, Vec<Envelope>>,
     pub public_observations: Vec<Observation>,
 }
 
 impl Event {
-    pub fn note(&mut self, pid: PlayerId, obs: Observation) {
-        self.private_observations.entry(pid).or_default().push(obs);
+    pub fn note(&mut self, pid: PlayerId, envelope: Envelope) {
+        self.observations.entry(pid).or_default().push(envelope);
     }
 
     pub fn broadcast(&mut self, obs: Observation) {
         self.public_observations.push(obs);
     }
+
+    /// Merge repeated observations of the same fact within a turn, keeping counts.
+    pub fn coalesce(&mut self) {
+        for (_, envelopes) in self.observations.iter_mut() {
+            coalesce(envelopes);
+        }
+    }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum IntelKind {
     HideSignals,
     Reveal,
     Invisible,
     Prepare,
+    Reinforce,
+    Informant,
 }
 
 impl IntelKind {
@@ -392,6 +1231,8 @@ impl IntelKind {
             IntelKind::Reveal => 1,
             IntelKind::Invisible => 2,
             IntelKind::Prepare => 0,
+            IntelKind::Reinforce => 1,
+            IntelKind::Informant => 1,
         }
     }
 }
@@ -399,5 +1240,7 @@ impl IntelKind {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// A player's action for a turn.
 pub enum Action {
-    Strike, Wait, Capture, HideSignals, Invisible, Prepare, Move(NodeIndex), Reveal(PlayerId),
+    Strike, Wait, Capture, HideSignals, Invisible, Prepare, Reinforce, Move(NodeIndex), Reveal(PlayerId), Informant(PlayerId),
+    /// End the turn early, giving up any remaining actions.
+    Pass,
 }
\ No newline at end of file
@@ -0,0 +1,107 @@
+use petgraph::graph::{NodeIndex, UnGraph};
+use serde::{Deserialize, Serialize};
+
+use crate::{Intel, Location};
+
+/// A board games can be played on: its locations, the roads between them, and where seats start.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Map {
+    pub name: String,
+    pub sites: Vec<Site>,
+    /// Undirected roads, as pairs of indices into `sites`.
+    pub roads: Vec<(usize, usize)>,
+    /// Starting site of each seat, in seating order. Also the most players the map holds.
+    pub starts: Vec<usize>,
+}
+
+/// One location on a `Map`, before any game has touched it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Site {
+    pub name: String,
+    pub base_income: Intel,
+    #[serde(default)]
+    pub boost: bool,
+    #[serde(default)]
+    pub pending_powerup: Option<Intel>,
+    /// Layout position for renderers, roughly in a unit square.
+    #[serde(default)]
+    pub pos: (f32, f32),
+}
+
+impl Map {
+    /// The default board: an eight-location ring with two crossings and four starting corners.
+    pub fn classic() -> Map {
+        let site = |name: &str, base_income, boost, pending_powerup, pos| Site {
+            name: name.into(),
+            base_income,
+            boost,
+            pending_powerup,
+            pos,
+        };
+        Map {
+            name: "classic".into(),
+            sites: vec![
+                site("Harbor", 1, false, None, (0.0, 0.0)),
+                site("Market", 2, false, None, (0.5, 0.0)),
+                site("Station", 1, false, None, (1.0, 0.0)),
+                site("Embassy", 2, true, None, (1.0, 0.5)),
+                site("Docks", 1, false, None, (1.0, 1.0)),
+                site("Archive", 2, false, Some(2), (0.5, 1.0)),
+                site("Cathedral", 1, false, None, (0.0, 1.0)),
+                site("Citadel", 3, true, None, (0.0, 0.5)),
+            ],
+            roads: vec![
+                (0, 1),
+                (1, 2),
+                (2, 3),
+                (3, 4),
+                (4, 5),
+                (5, 6),
+                (6, 7),
+                (7, 0),
+                (1, 5),
+                (3, 7),
+            ],
+            starts: vec![0, 4, 2, 6],
+        }
+    }
+
+    /// A built-in map by name.
+    pub fn named(name: &str) -> Option<Map> {
+        match name {
+            "classic" => Some(Map::classic()),
+            _ => None,
+        }
+    }
+
+    pub fn max_players(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Starting location of seat `seat`, if the map has room for it.
+    pub fn start(&self, seat: usize) -> Option<NodeIndex> {
+        self.starts.get(seat).map(|&s| NodeIndex::new(s))
+    }
+
+    /// Build the location graph; node indices match positions in `sites`.
+    pub fn graph(&self) -> UnGraph<Location, ()> {
+        let mut g = UnGraph::new_undirected();
+        for site in &self.sites {
+            let index = g.add_node(Location {
+                pending_powerup: site.pending_powerup,
+                boost: site.boost,
+                base_income: site.base_income,
+                name: site.name.clone(),
+                index: NodeIndex::default(),
+                control: None,
+                garrison: 0,
+                pos: site.pos,
+            });
+            g[index].index = index;
+        }
+        for &(a, b) in &self.roads {
+            g.add_edge(NodeIndex::new(a), NodeIndex::new(b), ());
+        }
+        g
+    }
+}
@@ -0,0 +1,122 @@
+//! JSON messages between the server and its clients: request bodies, endpoint answers, and
+//! everything the server pushes down a seat's event stream. The server, the web client and
+//! native clients all use these types, so none of them has to guess at another's shapes.
+//!
+//! Adding a `Push` variant or an optional field is compatible, since clients skip what they
+//! don't recognize. Anything else bumps `PROTOCOL_VERSION`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Envelope, PlayerId};
+
+/// Sent to every stream in `Push::Hello` before anything else.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Who a chat message is for.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// Everyone in the game.
+    #[default]
+    All,
+    /// Seats that joined with the same team.
+    Team,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub from: PlayerId,
+    pub channel: Channel,
+    /// The sender's team, for team messages.
+    pub team: Option<u32>,
+    pub text: String,
+    /// Game turn the message was sent during.
+    pub turn: u32,
+}
+
+/// Body of `POST /chat/{gid}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatRequest {
+    pub text: String,
+    #[serde(default)]
+    pub channel: Channel,
+}
+
+/// Answer to `POST /start_game`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Created {
+    pub gid: String,
+    /// Needed to join a private game.
+    pub join_code: Option<String>,
+}
+
+/// Answer to `POST /join_game/{gid}`: the seat taken and the token that acts as it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Joined {
+    pub pid: PlayerId,
+    pub token: String,
+}
+
+/// The seat a matchmaking ticket was matched into.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Matched {
+    pub gid: String,
+    pub pid: PlayerId,
+    pub token: String,
+}
+
+/// What a seat asked to do with a running game.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Vote {
+    Pause,
+    Resume,
+    End,
+}
+
+/// One message on a seat's event stream. Each is a JSON object with a single key naming the
+/// variant, e.g. `{"host": 2}`, except observations, which are a bare array of envelopes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Push {
+    /// First on every stream.
+    Hello {
+        version: u32,
+    },
+    /// Recent chat on subscribing, then each new message as it's sent.
+    Chat(Vec<ChatMessage>),
+    /// Other seats with a stream open, on subscribing.
+    Present(Vec<PlayerId>),
+    /// Another seat opened or lost its stream.
+    Presence {
+        pid: PlayerId,
+        connected: bool,
+    },
+    /// A seat voted to pause or resume, but it hasn't happened yet.
+    Asked {
+        by: PlayerId,
+        to: Vote,
+    },
+    Paused {
+        by: PlayerId,
+    },
+    Resumed {
+        by: PlayerId,
+    },
+    /// This stream's seat moved after someone left the lobby.
+    Seat {
+        pid: PlayerId,
+    },
+    /// The host removed this seat; nothing more will be sent.
+    Kicked(bool),
+    /// The seat now hosting the game.
+    Host(PlayerId),
+    /// Whether each seat is ready to begin, whenever a seat readies, unreadies, joins or leaves
+    /// the lobby.
+    Ready(Vec<bool>),
+    /// A queued ticket was matched into a game.
+    Matched(Matched),
+    /// Observations, in sequence order.
+    #[serde(untagged)]
+    Observed(Vec<Envelope>),
+}
@@ -0,0 +1,65 @@
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+use vecmap::VecMap;
+
+use crate::{ActionRecord, Game, GameError, Map, PlayerId, Profile, Ruleset};
+
+/// Bumped whenever `Replay` changes incompatibly.
+pub const REPLAY_VERSION: u32 = 1;
+
+/// A portable record of a match: everything needed to play it back from the start, since
+/// every random outcome follows from the seed.
+///
+/// Resignations aren't actions and aren't recorded, so a replay of a game someone resigned
+/// from diverges from that point.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub map: Map,
+    pub rules: Ruleset,
+    pub seed: u64,
+    /// Where each seat was placed, in seating order.
+    pub spawns: Vec<NodeIndex>,
+    pub profiles: VecMap<PlayerId, Profile>,
+    /// The game was played in turns; see `Game::begin`.
+    pub turns: bool,
+    pub actions: Vec<ActionRecord>,
+    /// The recorded result, for viewers that don't play the game back.
+    pub winner: Option<PlayerId>,
+}
+
+impl Game {
+    /// Record this game, played on `map`, as a replay.
+    pub fn replay(&self, map: &Map) -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            map: map.clone(),
+            rules: self.rules.clone(),
+            seed: self.seed,
+            spawns: self.history.spawns.clone(),
+            profiles: self.profiles.clone(),
+            turns: self.history.began,
+            actions: self.history.actions.clone(),
+            winner: self.winner(),
+        }
+    }
+}
+
+impl Replay {
+    /// Rebuild the game and play every recorded action, failing if any no longer resolves
+    /// (e.g. because the rules changed since it was recorded).
+    pub fn play(&self) -> Result<Game, GameError> {
+        let mut game = Game::seeded(self.rules.clone(), self.seed);
+        game.cities = self.map.graph();
+        for (pid, &at) in self.spawns.iter().enumerate() {
+            game.spawn_player(at, self.profiles.get(&pid).cloned());
+        }
+        if self.turns {
+            game.begin()?;
+        }
+        for record in &self.actions {
+            game.do_action(record.actor, record.action.clone())?;
+        }
+        Ok(game)
+    }
+}
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Intel;
+
+/// Tunable rules for a game. Defaults are the "classic" rules.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ruleset {
+    /// Intel trickled to a player who spends an action waiting.
+    pub wait_income: Intel,
+    /// Waiting restores concealment lost to a reveal.
+    pub wait_conceals: bool,
+    /// Largest garrison an owner may reinforce a location up to.
+    pub max_garrison: u32,
+    /// Alert raised by a strike.
+    pub alert_per_strike: u32,
+    /// Alert raised by a capture.
+    pub alert_per_capture: u32,
+    /// Alert level never rises above this.
+    pub max_alert: u32,
+    /// Quiet turns before the alert level drops by one.
+    pub alert_decay_turns: u32,
+    /// At or above this alert level, reveals are one intel cheaper.
+    pub alert_reveal_threshold: u32,
+    /// At or above this alert level, every strike's location is visible to everyone.
+    pub alert_violence_threshold: u32,
+    /// Locations held by a resigning player revert to neutral.
+    pub resign_releases_territory: bool,
+    /// Turns before an informant's report arrives.
+    pub informant_delay: u32,
+    /// Chance that a private location report is blurred into a two-location rumor.
+    pub noise: f64,
+    /// Beliefs older than this many turns are forgotten.
+    pub intel_expiry: Option<u32>,
+    /// Players within this many steps of a strike hear where it landed.
+    pub strike_noise_radius: u32,
+    /// Actions in a turn; starting on a boost location adds one.
+    pub actions_per_turn: u32,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Ruleset {
+            wait_income: 1,
+            wait_conceals: true,
+            max_garrison: 3,
+            alert_per_strike: 2,
+            alert_per_capture: 1,
+            max_alert: 6,
+            alert_decay_turns: 2,
+            alert_reveal_threshold: 3,
+            alert_violence_threshold: 5,
+            resign_releases_territory: true,
+            informant_delay: 2,
+            noise: 0.0,
+            intel_expiry: None,
+            strike_noise_radius: 1,
+            actions_per_turn: 2,
+        }
+    }
+}
+
+impl Ruleset {
+    /// Names of the built-in presets, for `Ruleset::named`.
+    pub const PRESETS: &'static [&'static str] = &["classic", "fog"];
+
+    /// A built-in preset: `classic`, or `fog`, where reports blur and old beliefs fade.
+    pub fn named(name: &str) -> Option<Ruleset> {
+        match name {
+            "classic" => Some(Ruleset::default()),
+            "fog" => Some(Ruleset {
+                noise: 0.25,
+                intel_expiry: Some(4),
+                ..Ruleset::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Check that the rules make a playable game, e.g. before accepting them from a client.
+    pub fn check(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.noise) {
+            return Err(format!("noise must be between 0 and 1, not {}", self.noise));
+        }
+        if self.actions_per_turn == 0 {
+            return Err("actions_per_turn must be at least 1".into());
+        }
+        // Bounds generous for play but far from overflowing the engine's counters.
+        let limits = [
+            ("wait_income", self.wait_income, 1_000),
+            ("max_garrison", self.max_garrison, 1_000),
+            ("alert_per_strike", self.alert_per_strike, 1_000),
+            ("alert_per_capture", self.alert_per_capture, 1_000),
+            ("max_alert", self.max_alert, 1_000),
+            ("alert_decay_turns", self.alert_decay_turns, 1_000),
+            ("informant_delay", self.informant_delay, 1_000),
+            ("intel_expiry", self.intel_expiry.unwrap_or_default(), 1_000),
+            ("strike_noise_radius", self.strike_noise_radius, 1_000),
+            ("actions_per_turn", self.actions_per_turn, 100),
+        ];
+        for (name, value, max) in limits {
+            if value > max {
+                return Err(format!("{name} must be at most {max}, not {value}"));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+//! Sealing a player's private observations so untrusted relays can't read them.
+//!
+//! Peers are identified by ed25519 keys (an iroh `NodeId` is one). Sealing converts the
+//! recipient's key to its x25519 form and encrypts the wire-encoded envelopes in an anonymous
+//! sealed box: anyone may seal to a player, but only that player's secret key can open it.
+
+use crypto_box::{PublicKey, SecretKey};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use crate::wire::{self, WireError};
+use crate::Envelope;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SealError {
+    /// The recipient's public key is not a valid ed25519 point.
+    BadKey,
+    /// Encryption failed, or the box was not sealed to this key or was tampered with.
+    Crypto,
+    /// The box opened, but did not hold a valid stream of envelopes.
+    Wire(WireError),
+}
+
+/// Encrypt `envelopes` so that only the holder of the ed25519 key `recipient` can read them.
+pub fn seal(recipient: &[u8; 32], envelopes: &[Envelope]) -> Result<Vec<u8>, SealError> {
+    let key = VerifyingKey::from_bytes(recipient).map_err(|_| SealError::BadKey)?;
+    let key = PublicKey::from(key.to_montgomery().to_bytes());
+    key.seal(&mut rand::rngs::OsRng, &wire::encode_envelopes(envelopes))
+        .map_err(|_| SealError::Crypto)
+}
+
+/// Open a box sealed to the ed25519 secret key `secret`.
+pub fn open(secret: &[u8; 32], sealed: &[u8]) -> Result<Vec<Envelope>, SealError> {
+    let key = SecretKey::from(SigningKey::from_bytes(secret).to_scalar_bytes());
+    let plaintext = key.unseal(sealed).map_err(|_| SealError::Crypto)?;
+    wire::decode_envelopes(&plaintext).map_err(SealError::Wire)
+}
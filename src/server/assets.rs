@@ -0,0 +1,78 @@
+//! The web client's files, compiled into the binary so the server runs from anywhere.
+
+use actix_web::http::header::{self, ContentType};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+
+use crate::error::{ServerError, ServerResult};
+
+struct Asset {
+    name: &'static str,
+    content_type: &'static str,
+    body: &'static [u8],
+}
+
+/// Everything under `/static/`, plus `index.html` at `/`.
+const ASSETS: &[Asset] = &[Asset {
+    name: "index.html",
+    content_type: "text/html; charset=utf-8",
+    body: include_bytes!("../index.html"),
+}];
+
+impl Asset {
+    fn named(name: &str) -> Option<&'static Asset> {
+        ASSETS.iter().find(|asset| asset.name == name)
+    }
+
+    /// Changes whenever the file does, so caches can revalidate cheaply.
+    fn etag(&self) -> String {
+        // FNV-1a; only needs to tell builds apart.
+        let hash = self.body.iter().fold(0xcbf29ce484222325u64, |hash, &b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        format!("\"{hash:016x}\"")
+    }
+
+    fn serve(&self, req: &HttpRequest) -> HttpResponse {
+        let etag = self.etag();
+        // Pages are revalidated every time so a new build takes effect at once; what they load
+        // can be kept for a day.
+        let cache = if self.content_type.starts_with("text/html") {
+            "no-cache"
+        } else {
+            "public, max-age=86400"
+        };
+        let fresh = req
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|tag| tag.to_str().ok())
+            .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag));
+        let mut response = if fresh {
+            HttpResponse::NotModified()
+        } else {
+            HttpResponse::Ok()
+        };
+        response
+            .insert_header((header::ETAG, etag))
+            .insert_header((header::CACHE_CONTROL, cache));
+        if fresh {
+            return response.finish();
+        }
+        response
+            .insert_header(ContentType(self.content_type.parse().unwrap()))
+            .body(self.body)
+    }
+}
+
+#[get("/")]
+pub async fn index(req: HttpRequest) -> ServerResult<HttpResponse> {
+    Asset::named("index.html")
+        .map(|asset| asset.serve(&req))
+        .ok_or_else(|| ServerError::Internal("index.html wasn't built in".into()))
+}
+
+#[get("/static/{name}")]
+pub async fn static_file(req: HttpRequest, name: web::Path<String>) -> ServerResult<HttpResponse> {
+    Asset::named(&name)
+        .map(|asset| asset.serve(&req))
+        .ok_or(ServerError::NoSuchAsset)
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use actix_web::rt::time;
+use actix_web::web::{self, Data};
+use actix_web::{post, HttpRequest, HttpResponse};
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+use crate::error::ServerResult;
+use crate::{limit, parse_gid, shared, GameState, State, Status};
+
+/// Pause before each bot action, so people can follow what it does.
+const THINK: Duration = Duration::from_millis(700);
+
+#[derive(Deserialize)]
+pub struct BotQuery {
+    #[serde(default)]
+    level: esgea::BotLevel,
+}
+
+impl GameState {
+    /// Take one action for the active player if it's a bot.
+    fn play_bot(&mut self) {
+        let _game = self.span().entered();
+        let mut game = self.game.lock();
+        let Some(pid) = game.active else { return };
+        let Some(&level) = self.bots.get(&pid) else {
+            return;
+        };
+        let action = game.bot_action(pid, level, &mut rand::thread_rng());
+        let played = game
+            .do_action(pid, action.clone())
+            .or_else(|_| game.do_action(pid, esgea::Action::Wait))
+            .or_else(|_| game.do_action(pid, esgea::Action::Pass));
+        match played {
+            Ok(id) => {
+                tracing::info!(pid, ?level, ?action, id = ?id, "bot action");
+                crate::stats::acted();
+            }
+            Err(e) => tracing::warn!(pid, ?level, error = ?e, "bot couldn't act"),
+        }
+        drop(game);
+        self.touch();
+        self.distribute_updates();
+    }
+}
+
+/// Play bots' turns in `gid` whenever they come up, until the game is over or gone or has no
+/// bots left, unless a task already does. Call with the game locked, as `gs`.
+pub fn spawn(state: Data<Mutex<State>>, gid: u128, gs: &mut GameState) {
+    if gs.bot_driver {
+        return;
+    }
+    gs.bot_driver = true;
+    actix_web::rt::spawn(async move {
+        loop {
+            time::sleep(THINK).await;
+            let Ok(slot) = state.lock().game(gid) else {
+                return;
+            };
+            let mut gs = slot.lock();
+            let over = matches!(gs.status, Status::Finished | Status::Abandoned);
+            if over || gs.bots.is_empty() {
+                gs.bot_driver = false;
+                return;
+            }
+            if gs.status == Status::Running {
+                gs.play_bot();
+            }
+        }
+    });
+}
+
+/// Seat a computer player at `?level=easy|normal|hard` (default normal). Only players already
+/// seated in the game may add bots, which are always ready.
+#[post("/add_bot/{gid}")]
+pub async fn add_bot(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<BotQuery>,
+    limits: Data<limit::Limits>,
+) -> ServerResult<HttpResponse> {
+    limits.lobby.check(&req)?;
+    let gid = parse_gid(&path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    gs.identify(gid, &req)?;
+    gs.open_seat()?;
+    let (pid, _) = gs.seat(None);
+    gs.game.lock().profiles.insert(
+        pid,
+        esgea::Profile {
+            name: format!("{:?} bot", query.level),
+            color: None,
+        },
+    );
+    gs.bots.insert(pid, query.level);
+    gs.ready[pid] = true;
+    gs.tell_ready();
+    shared::save(&gs);
+    spawn(state.clone(), gid, &mut gs);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "pid": pid })))
+}
@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use actix_web::web::{self, Data, Json};
+use actix_web::{get, post, HttpRequest, HttpResponse};
+use esgea::protocol::{Channel, ChatMessage, ChatRequest, Push};
+use parking_lot::Mutex;
+
+use crate::error::{ServerError, ServerResult};
+use crate::{parse_gid, shared, GameState, State};
+
+/// Longest message, in characters.
+const MAX_LEN: usize = 500;
+/// Messages kept for players who (re)connect.
+const HISTORY: usize = 100;
+/// A seat may send at most `BURST` messages per `WINDOW`.
+const BURST: usize = 5;
+const WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+pub struct ChatLog {
+    recent: VecDeque<ChatMessage>,
+    /// When each seat's recent messages were sent, for rate limiting.
+    sent: BTreeMap<esgea::PlayerId, VecDeque<Instant>>,
+}
+
+impl ChatLog {
+    /// Forget `pid`'s messages and move later seats down one, after `pid` leaves the lobby.
+    pub(crate) fn unseat(&mut self, pid: esgea::PlayerId) {
+        self.recent.retain(|msg| msg.from != pid);
+        for msg in &mut self.recent {
+            if msg.from > pid {
+                msg.from -= 1;
+            }
+        }
+        self.sent = std::mem::take(&mut self.sent)
+            .into_iter()
+            .filter(|&(from, _)| from != pid)
+            .map(|(from, sent)| (if from > pid { from - 1 } else { from }, sent))
+            .collect();
+    }
+}
+
+impl GameState {
+    fn can_read(&self, pid: esgea::PlayerId, msg: &ChatMessage) -> bool {
+        match msg.channel {
+            Channel::All => true,
+            Channel::Team => self.teams.get(pid).copied().flatten() == msg.team,
+        }
+    }
+
+    /// Recent messages `pid` is allowed to read, oldest first.
+    pub(crate) fn chat_for(&self, pid: esgea::PlayerId) -> Vec<ChatMessage> {
+        self.chat
+            .recent
+            .iter()
+            .filter(|msg| self.can_read(pid, msg))
+            .cloned()
+            .collect()
+    }
+
+    fn say(&mut self, from: esgea::PlayerId, request: ChatRequest) -> ServerResult<()> {
+        let text = request.text.trim();
+        if text.is_empty() || text.chars().count() > MAX_LEN {
+            return Err(ServerError::BadChat(format!(
+                "messages are 1 to {MAX_LEN} characters"
+            )));
+        }
+        let team = self.teams.get(from).copied().flatten();
+        if request.channel == Channel::Team && team.is_none() {
+            return Err(ServerError::BadChat("you didn't join with a team".into()));
+        }
+        let now = Instant::now();
+        let sent = self.chat.sent.entry(from).or_default();
+        while sent
+            .front()
+            .is_some_and(|&t| now.duration_since(t) > WINDOW)
+        {
+            sent.pop_front();
+        }
+        if sent.len() >= BURST {
+            return Err(ServerError::RateLimited);
+        }
+        sent.push_back(now);
+
+        let msg = ChatMessage {
+            from,
+            channel: request.channel,
+            team: team.filter(|_| request.channel == Channel::Team),
+            text: text.to_owned(),
+            turn: self.game.lock().turn,
+        };
+        let json = serde_json::to_string(&Push::Chat(vec![msg.clone()])).unwrap_or_default();
+        for pid in 0..self.pid_channels.len() {
+            if self.can_read(pid, &msg) {
+                self.deliver(pid, &json);
+            }
+        }
+        self.chat.recent.push_back(msg);
+        if self.chat.recent.len() > HISTORY {
+            self.chat.recent.pop_front();
+        }
+        Ok(())
+    }
+}
+
+/// Send `{text, channel}` as the seat the request identifies, to everyone or to its team.
+#[post("/chat/{gid}")]
+pub async fn send_chat(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: Json<ChatRequest>,
+) -> ServerResult<HttpResponse> {
+    let gid = parse_gid(&path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    let pid = gs.identify(gid, &req)?;
+    gs.say(pid, body.into_inner())?;
+    Ok(HttpResponse::Ok().body(()))
+}
+
+/// Recent messages the requesting seat can read.
+#[get("/chat/{gid}")]
+pub async fn recent_chat(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> ServerResult<HttpResponse> {
+    let gid = parse_gid(&path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let gs = slot.lock();
+    let pid = gs.identify(gid, &req)?;
+    Ok(HttpResponse::Ok().json(gs.chat_for(pid)))
+}
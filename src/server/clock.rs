@@ -0,0 +1,59 @@
+use std::time::Instant;
+
+use actix_web::rt::time;
+use actix_web::web::Data;
+use parking_lot::Mutex;
+
+use crate::{GameState, State, Status};
+
+impl GameState {
+    /// Restart the turn clock if the turn has changed hands since it was last wound.
+    pub(crate) fn wind_clock(&mut self) {
+        let Some(limit) = self.turn_limit else { return };
+        let turn = self.game.lock().turn;
+        if self.status == Status::Running && self.clock_turn != turn {
+            self.clock_turn = turn;
+            self.deadline = Some(Instant::now() + limit);
+        }
+    }
+}
+
+/// Skip the active player's turn whenever they let `gid`'s turn clock run out, until the game
+/// is over or gone.
+pub fn spawn(state: Data<Mutex<State>>, gid: u128) {
+    actix_web::rt::spawn(async move {
+        loop {
+            let deadline = {
+                let Ok(slot) = state.lock().game(gid) else {
+                    return;
+                };
+                let gs = slot.lock();
+                let (Some(limit), Status::Lobby | Status::Running | Status::Paused) =
+                    (gs.turn_limit, gs.status)
+                else {
+                    return;
+                };
+                gs.deadline.unwrap_or_else(|| Instant::now() + limit)
+            };
+            time::sleep_until(deadline.into()).await;
+
+            let Ok(slot) = state.lock().game(gid) else {
+                return;
+            };
+            let mut gs = slot.lock();
+            if gs.status != Status::Running || gs.deadline.is_none_or(|d| d > Instant::now()) {
+                continue;
+            }
+            let _game = gs.span().entered();
+            let timed_out = gs.game.lock().time_out();
+            if let Err(e) = timed_out {
+                tracing::warn!(error = ?e, "couldn't time out the turn");
+                gs.deadline = None;
+                continue;
+            }
+            tracing::info!("turn timed out");
+            gs.touch();
+            gs.distribute_updates();
+        }
+    });
+}
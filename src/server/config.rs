@@ -0,0 +1,375 @@
+use std::fs::File;
+use std::io::{BufReader, IsTerminal};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use actix_web::cookie::Key;
+use actix_web::http::header::{self, HeaderName};
+use actix_web::http::Uri;
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+/// Where the config file is looked for when `ESGEA_CONFIG` doesn't name one.
+const DEFAULT_PATH: &str = "esgead.toml";
+
+/// Server settings, read from a TOML file and then overridden by `ESGEA_*` environment
+/// variables.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address to listen on; loopback only unless set, e.g. to `0.0.0.0:8080`. `ESGEA_LISTEN`.
+    pub listen: String,
+    /// Also serve the gRPC API on this address, as plain HTTP/2; put a TLS proxy in front of
+    /// it if it's exposed. Needs the `grpc` feature. `ESGEA_GRPC_LISTEN`.
+    pub grpc_listen: Option<String>,
+    /// Serve HTTPS with this certificate and key instead of plain HTTP. `ESGEA_TLS_CERT` and
+    /// `ESGEA_TLS_KEY`.
+    pub tls: Option<Tls>,
+    /// File the session cookie key is kept in, so sessions survive a restart. A key is
+    /// generated and written there if it doesn't exist; without it every start invalidates
+    /// existing sessions. `ESGEA_SESSION_KEY`.
+    pub session_key: Option<PathBuf>,
+    /// `tracing` filter directives such as `info,esgead=debug`, used when `RUST_LOG` isn't
+    /// set. `ESGEA_LOG`.
+    pub log: String,
+    /// How log lines are laid out. `ESGEA_LOG_FORMAT`.
+    pub log_format: LogFormat,
+    pub storage: Storage,
+    /// File player accounts and ratings are kept in; without it they're lost on restart.
+    /// `ESGEA_RATINGS`.
+    pub ratings: Option<PathBuf>,
+    /// Rules new games are played by.
+    pub ruleset: esgea::Ruleset,
+    pub cors: Cors,
+    pub notify: Notify,
+    pub proxies: Proxies,
+}
+
+/// Pages on other origins allowed to call the API, e.g. the web client hosted elsewhere.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cors {
+    /// Origins such as `https://play.example.com`, or `*` for any. Without any, only pages the
+    /// server itself serves may call it. `ESGEA_CORS_ORIGINS`, comma-separated.
+    pub origins: Vec<String>,
+    /// Let those pages send the session cookie, which is then marked `SameSite=None` and so
+    /// only sent over HTTPS. Can't be combined with `*`. `ESGEA_CORS_CREDENTIALS`.
+    pub credentials: bool,
+}
+
+/// Turn notifications players may ask for; see `notify`.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Notify {
+    /// Hosts hooks may point at, e.g. `matrix.org`, or `*` for any. Without any, nobody can
+    /// register a hook. `ESGEA_NOTIFY_HOSTS`, comma-separated.
+    pub hosts: Vec<String>,
+}
+
+/// Reverse proxies in front of the server, trusted to say which client they forwarded a request
+/// for; rate limits go by that client rather than the proxy.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Proxies {
+    /// The proxies' addresses, e.g. `127.0.0.1`. A request from anywhere else counts against
+    /// its own address, whatever its headers claim. `ESGEA_TRUSTED_PROXIES`, comma-separated.
+    pub trusted: Vec<IpAddr>,
+    /// Header the proxies append the address they heard from to. `ESGEA_FORWARDED_HEADER`.
+    pub header: String,
+}
+
+impl Default for Proxies {
+    fn default() -> Self {
+        Proxies {
+            trusted: vec![],
+            header: "x-forwarded-for".into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    /// PEM certificate chain.
+    pub cert: PathBuf,
+    /// PEM private key.
+    pub key: PathBuf,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One line per event, with the spans it happened in.
+    #[default]
+    Full,
+    /// Shorter lines, for terminals.
+    Compact,
+    /// Several lines per event, for reading by eye.
+    Pretty,
+}
+
+/// Where games live.
+#[derive(Default, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Storage {
+    /// In this process only.
+    #[default]
+    Memory,
+    /// Shared with other replicas through Redis; needs the `redis` feature. Setting
+    /// `ESGEA_REDIS_URL` selects this.
+    Redis { url: String },
+}
+
+impl Tls {
+    /// A rustls server config serving the certificate chain and key from these files.
+    pub fn server_config(&self) -> Result<rustls::ServerConfig, String> {
+        let certs = rustls_pemfile::certs(&mut pem(&self.cert)?)
+            .map_err(|e| format!("reading {}: {e}", self.cert.display()))?
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+        let key = rustls_pemfile::read_all(&mut pem(&self.key)?)
+            .map_err(|e| format!("reading {}: {e}", self.key.display()))?
+            .into_iter()
+            .find_map(|item| match item {
+                rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+                _ => None,
+            })
+            .ok_or_else(|| format!("no private key in {}", self.key.display()))?;
+        rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| format!("loading TLS certificate: {e}"))
+    }
+}
+
+impl Cors {
+    fn check(&self) -> Result<(), String> {
+        for origin in &self.origins {
+            if origin == "*" {
+                if self.credentials {
+                    return Err("CORS credentials can't be allowed for any origin (`*`)".into());
+                }
+                continue;
+            }
+            let uri: Uri = origin
+                .parse()
+                .map_err(|e| format!("CORS origin {origin:?}: {e}"))?;
+            if uri.scheme().is_none() || uri.host().is_none() {
+                return Err(format!(
+                    "CORS origin {origin:?} needs a scheme and host, e.g. https://example.com"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Middleware answering preflights and tagging responses for the allowed origins.
+    pub fn middleware(&self) -> actix_cors::Cors {
+        let mut cors = actix_cors::Cors::default()
+            .allowed_methods(["GET", "POST", "DELETE"])
+            .allowed_headers([header::AUTHORIZATION, header::CONTENT_TYPE, header::ACCEPT])
+            .max_age(3600)
+            // The server's own pages send an `Origin` too, on POSTs.
+            .allowed_origin_fn(|origin, req| {
+                let host = req.headers.get(header::HOST).and_then(|h| h.to_str().ok());
+                let origin = origin.to_str().ok().and_then(|o| o.split_once("://"));
+                matches!((origin, host), (Some((_, origin)), Some(host)) if origin == host)
+            });
+        for origin in &self.origins {
+            cors = match origin.as_str() {
+                "*" => cors.allow_any_origin(),
+                origin => cors.allowed_origin(origin),
+            };
+        }
+        if self.credentials {
+            cors = cors.supports_credentials();
+        }
+        cors
+    }
+}
+
+impl Proxies {
+    /// Check the header is one that can be sent, and spell it in lowercase as gRPC metadata
+    /// keys must be.
+    fn check(&mut self) -> Result<(), String> {
+        let name = HeaderName::from_bytes(self.header.as_bytes())
+            .map_err(|_| format!("proxies.header {:?} isn't a header name", self.header))?;
+        self.header = name.as_str().to_owned();
+        Ok(())
+    }
+}
+
+fn pem(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| format!("opening {}: {e}", path.display()))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: "127.0.0.1:8080".into(),
+            grpc_listen: None,
+            tls: None,
+            session_key: None,
+            log: "info".into(),
+            log_format: LogFormat::Full,
+            storage: Storage::Memory,
+            ratings: None,
+            ruleset: esgea::Ruleset::default(),
+            cors: Cors::default(),
+            notify: Notify::default(),
+            proxies: Proxies::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Read the file named by `ESGEA_CONFIG` (or `esgead.toml`, if present), then apply
+    /// environment overrides.
+    pub fn load() -> Result<Config, String> {
+        let env = |name: &str| std::env::var(name).ok();
+        let mut config = match env("ESGEA_CONFIG") {
+            Some(path) => Config::read(&path)?,
+            None if Path::new(DEFAULT_PATH).exists() => Config::read(DEFAULT_PATH)?,
+            None => Config::default(),
+        };
+        if let Some(listen) = env("ESGEA_LISTEN") {
+            config.listen = listen;
+        }
+        if let Some(listen) = env("ESGEA_GRPC_LISTEN") {
+            config.grpc_listen = Some(listen);
+        }
+        config
+            .ruleset
+            .check()
+            .map_err(|e| format!("ruleset: {e}"))?;
+        if config.grpc_listen.is_some() && !cfg!(feature = "grpc") {
+            return Err("grpc_listen needs a server built with the `grpc` feature".into());
+        }
+        match (env("ESGEA_TLS_CERT"), env("ESGEA_TLS_KEY")) {
+            (Some(cert), Some(key)) => {
+                config.tls = Some(Tls {
+                    cert: cert.into(),
+                    key: key.into(),
+                })
+            }
+            (None, None) => {}
+            _ => return Err("ESGEA_TLS_CERT and ESGEA_TLS_KEY must be set together".into()),
+        }
+        if let Some(path) = env("ESGEA_SESSION_KEY") {
+            config.session_key = Some(path.into());
+        }
+        if let Some(log) = env("ESGEA_LOG") {
+            config.log = log;
+        }
+        if let Some(format) = env("ESGEA_LOG_FORMAT") {
+            config.log_format = match format.as_str() {
+                "full" => LogFormat::Full,
+                "compact" => LogFormat::Compact,
+                "pretty" => LogFormat::Pretty,
+                _ => {
+                    return Err(format!(
+                        "ESGEA_LOG_FORMAT={format:?}: expected full, compact or pretty"
+                    ))
+                }
+            };
+        }
+        if let Some(path) = env("ESGEA_RATINGS") {
+            config.ratings = Some(path.into());
+        }
+        if let Some(url) = env("ESGEA_REDIS_URL") {
+            config.storage = Storage::Redis { url };
+        }
+        if let Some(origins) = env("ESGEA_CORS_ORIGINS") {
+            config.cors.origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_owned)
+                .collect();
+        }
+        if let Some(credentials) = env("ESGEA_CORS_CREDENTIALS") {
+            config.cors.credentials = credentials.parse().map_err(|_| {
+                format!("ESGEA_CORS_CREDENTIALS={credentials:?}: expected true or false")
+            })?;
+        }
+        config.cors.check()?;
+        if let Some(hosts) = env("ESGEA_NOTIFY_HOSTS") {
+            config.notify.hosts = hosts
+                .split(',')
+                .map(str::trim)
+                .filter(|host| !host.is_empty())
+                .map(str::to_owned)
+                .collect();
+        }
+        if !config.notify.hosts.is_empty() && !cfg!(feature = "notify") {
+            return Err("notify.hosts needs a server built with the `notify` feature".into());
+        }
+        if let Some(proxies) = env("ESGEA_TRUSTED_PROXIES") {
+            config.proxies.trusted = proxies
+                .split(',')
+                .map(str::trim)
+                .filter(|proxy| !proxy.is_empty())
+                .map(|proxy| {
+                    proxy
+                        .parse()
+                        .map_err(|e| format!("ESGEA_TRUSTED_PROXIES: {proxy:?}: {e}"))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(header) = env("ESGEA_FORWARDED_HEADER") {
+            config.proxies.header = header;
+        }
+        config.proxies.check()?;
+        Ok(config)
+    }
+
+    /// Send `tracing` events, and `log` records from dependencies, to stderr as configured.
+    pub fn start_logging(&self) -> Result<(), String> {
+        let filter = match EnvFilter::try_from_default_env() {
+            Ok(filter) => filter,
+            Err(_) => EnvFilter::try_new(&self.log).map_err(|e| format!("log filter: {e}"))?,
+        };
+        let fmt = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(std::io::stderr)
+            .with_ansi(std::io::stderr().is_terminal());
+        let started = match self.log_format {
+            LogFormat::Full => fmt.try_init(),
+            LogFormat::Compact => fmt.compact().try_init(),
+            LogFormat::Pretty => fmt.pretty().try_init(),
+        };
+        started.map_err(|e| format!("starting logging: {e}"))
+    }
+
+    fn read(path: &str) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("reading {path}: {e}"))?;
+        toml::from_str(&text).map_err(|e| format!("parsing {path}: {e}"))
+    }
+
+    /// The session cookie key: loaded from `session_key`, created there on first start, or
+    /// generated afresh if no file is configured.
+    pub fn session_key(&self) -> Result<Key, String> {
+        let Some(path) = &self.session_key else {
+            return Ok(Key::generate());
+        };
+        let shown = path.display();
+        match std::fs::read(path) {
+            Ok(bytes) => {
+                Key::try_from(&bytes[..]).map_err(|e| format!("session key in {shown}: {e}"))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key = Key::generate();
+                std::fs::write(path, key.master())
+                    .map_err(|e| format!("writing session key to {shown}: {e}"))?;
+                Ok(key)
+            }
+            Err(e) => Err(format!("reading session key from {shown}: {e}")),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use esgea::GameError;
+
+/// Why a request failed. Rendered as `{"error": <kind>, "detail": <message>}`.
+#[derive(Debug)]
+pub enum ServerError {
+    /// A path segment that should have been a game or player id wasn't a number.
+    BadId(String),
+    /// The request body wasn't a JSON `esgea::Action`.
+    BadAction(String),
+    /// The request body wasn't valid JSON `GameOptions`.
+    BadOptions(String),
+    /// The request body wasn't the JSON the endpoint expects.
+    BadBody(String),
+    /// A chat message was empty, too long, or for a channel the sender can't use.
+    BadChat(String),
+    /// A turn notification hook this server won't call.
+    BadHook(String),
+    NoSuchMap(String),
+    NoSuchRuleset(String),
+    /// No built-in web asset by that name.
+    NoSuchAsset,
+    NoSuchGame,
+    /// A private game was joined without its join code.
+    BadJoinCode,
+    /// Every seat is taken.
+    GameFull,
+    /// The matchmaking ticket was never issued, or has been used up.
+    NoSuchTicket,
+    NoSuchTournament,
+    NoSuchAccount,
+    /// Someone already has an account, or a place in the tournament, by that name.
+    NameTaken,
+    /// The tournament has already started.
+    RegistrationClosed,
+    /// Only the tournament's creator may do that.
+    NotOrganizer,
+    /// Only the game's host may do that.
+    NotHost,
+    /// The game can't begin yet.
+    NotReady(String),
+    /// The host tried to kick a seat that can't be kicked.
+    CantKick(String),
+    /// The request didn't carry the seat's token.
+    NotYourSeat,
+    /// The game isn't accepting actions in its current lifecycle state.
+    NotRunning,
+    /// The game has begun, so no one else can join.
+    AlreadyStarted,
+    /// Only a paused game can be resumed.
+    NotPaused,
+    /// The game has finished or been abandoned.
+    GameOver,
+    /// The game is still being played, so its full record would give away hidden moves.
+    NotOver,
+    /// Too many requests in too short a time.
+    RateLimited,
+    /// The engine rejected the action.
+    Game(GameError),
+    /// Something went wrong on our side, e.g. the renderer failed.
+    Internal(String),
+}
+
+pub type ServerResult<T> = Result<T, ServerError>;
+
+impl ServerError {
+    fn kind(&self) -> String {
+        match self {
+            ServerError::BadId(_) => "BadId".into(),
+            ServerError::BadAction(_) => "BadAction".into(),
+            ServerError::BadOptions(_) => "BadOptions".into(),
+            ServerError::BadBody(_) => "BadBody".into(),
+            ServerError::BadChat(_) => "BadChat".into(),
+            ServerError::BadHook(_) => "BadHook".into(),
+            ServerError::RateLimited => "RateLimited".into(),
+            ServerError::NoSuchMap(_) => "NoSuchMap".into(),
+            ServerError::NoSuchRuleset(_) => "NoSuchRuleset".into(),
+            ServerError::NoSuchAsset => "NoSuchAsset".into(),
+            ServerError::NoSuchGame => "NoSuchGame".into(),
+            ServerError::BadJoinCode => "BadJoinCode".into(),
+            ServerError::GameFull => "GameFull".into(),
+            ServerError::NoSuchTicket => "NoSuchTicket".into(),
+            ServerError::NoSuchTournament => "NoSuchTournament".into(),
+            ServerError::NoSuchAccount => "NoSuchAccount".into(),
+            ServerError::NameTaken => "NameTaken".into(),
+            ServerError::RegistrationClosed => "RegistrationClosed".into(),
+            ServerError::NotOrganizer => "NotOrganizer".into(),
+            ServerError::NotHost => "NotHost".into(),
+            ServerError::NotReady(_) => "NotReady".into(),
+            ServerError::CantKick(_) => "CantKick".into(),
+            ServerError::NotYourSeat => "NotYourSeat".into(),
+            ServerError::NotRunning => "NotRunning".into(),
+            ServerError::AlreadyStarted => "AlreadyStarted".into(),
+            ServerError::NotPaused => "NotPaused".into(),
+            ServerError::GameOver => "GameOver".into(),
+            ServerError::NotOver => "NotOver".into(),
+            ServerError::Game(e) => format!("{e:?}"),
+            ServerError::Internal(_) => "Internal".into(),
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::BadId(id) => write!(f, "{id:?} is not a valid id"),
+            ServerError::BadAction(e) => write!(f, "not an action: {e}"),
+            ServerError::BadOptions(e) => write!(f, "bad game options: {e}"),
+            ServerError::BadBody(e) => write!(f, "bad request body: {e}"),
+            ServerError::BadChat(e) => write!(f, "can't send that: {e}"),
+            ServerError::BadHook(e) => write!(f, "can't notify that: {e}"),
+            ServerError::RateLimited => write!(f, "slow down"),
+            ServerError::NoSuchMap(name) => write!(f, "no map called {name:?}"),
+            ServerError::NoSuchRuleset(name) => write!(
+                f,
+                "no rules preset called {name:?}; try one of {:?}",
+                esgea::Ruleset::PRESETS
+            ),
+            ServerError::NoSuchGame => write!(f, "no such game"),
+            ServerError::NoSuchAsset => write!(f, "no such file"),
+            ServerError::BadJoinCode => write!(f, "wrong join code"),
+            ServerError::GameFull => write!(f, "the game is full"),
+            ServerError::NoSuchTicket => write!(f, "no such matchmaking ticket"),
+            ServerError::NoSuchTournament => write!(f, "no such tournament"),
+            ServerError::NoSuchAccount => write!(f, "no such account"),
+            ServerError::NameTaken => write!(f, "that name is taken"),
+            ServerError::RegistrationClosed => write!(f, "the tournament has already started"),
+            ServerError::NotOrganizer => write!(f, "only the organizer can do that"),
+            ServerError::NotHost => write!(f, "only the host can do that"),
+            ServerError::NotReady(e) => write!(f, "not ready to begin: {e}"),
+            ServerError::CantKick(e) => write!(f, "can't kick: {e}"),
+            ServerError::NotYourSeat => write!(f, "not your seat"),
+            ServerError::NotRunning => write!(f, "the game is not running"),
+            ServerError::AlreadyStarted => write!(f, "the game has already started"),
+            ServerError::NotPaused => write!(f, "the game isn't paused"),
+            ServerError::GameOver => write!(f, "the game is over"),
+            ServerError::NotOver => write!(f, "the game isn't over yet"),
+            ServerError::Game(e) => write!(f, "rejected by the game: {e:?}"),
+            ServerError::Internal(e) => write!(f, "internal error: {e}"),
+        }
+    }
+}
+
+impl From<GameError> for ServerError {
+    fn from(e: GameError) -> Self {
+        ServerError::Game(e)
+    }
+}
+
+impl ResponseError for ServerError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ServerError::BadId(_)
+            | ServerError::BadAction(_)
+            | ServerError::BadOptions(_)
+            | ServerError::BadBody(_)
+            | ServerError::BadChat(_)
+            | ServerError::BadHook(_) => StatusCode::BAD_REQUEST,
+            ServerError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ServerError::NoSuchGame
+            | ServerError::NoSuchMap(_)
+            | ServerError::NoSuchRuleset(_)
+            | ServerError::NoSuchAsset
+            | ServerError::NoSuchTicket
+            | ServerError::NoSuchTournament
+            | ServerError::NoSuchAccount => StatusCode::NOT_FOUND,
+            ServerError::NotYourSeat
+            | ServerError::BadJoinCode
+            | ServerError::NotOrganizer
+            | ServerError::NotHost => StatusCode::FORBIDDEN,
+            ServerError::NotRunning
+            | ServerError::NameTaken
+            | ServerError::RegistrationClosed
+            | ServerError::AlreadyStarted
+            | ServerError::NotPaused
+            | ServerError::NotReady(_)
+            | ServerError::CantKick(_)
+            | ServerError::GameOver
+            | ServerError::NotOver
+            | ServerError::GameFull => StatusCode::CONFLICT,
+            ServerError::Game(GameError::NotYourTurn) => StatusCode::CONFLICT,
+            ServerError::Game(_) => StatusCode::BAD_REQUEST,
+            ServerError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.kind(),
+            "detail": self.to_string(),
+        }))
+    }
+}
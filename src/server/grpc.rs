@@ -0,0 +1,198 @@
+//! The core of the API over gRPC, for bots and other programmatic clients that would rather
+//! have typed calls and streams than websockets. The service is generated from
+//! `proto/esgea.proto` and shares its logic with the REST handlers; errors carry the same
+//! detail messages, with status codes mapped across.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use actix_web::web::Data;
+use actix_web::ResponseError;
+use futures_util::{Stream, StreamExt};
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use esgea::protocol::Push;
+
+use crate::config::Proxies;
+use crate::error::{ServerError, ServerResult};
+use crate::lobby::GameOptions;
+use crate::push::Subscriber;
+use crate::{create_game, limit, parse_gid, shared, State};
+
+mod pb {
+    tonic::include_proto!("esgea.v1");
+}
+
+use pb::esgea_server::{Esgea, EsgeaServer};
+
+/// Messages a stream may fall behind by before it's dropped.
+const BACKLOG: usize = 64;
+
+impl From<ServerError> for Status {
+    fn from(e: ServerError) -> Status {
+        let message = e.to_string();
+        match e.status_code().as_u16() {
+            400 => Status::invalid_argument(message),
+            403 => Status::permission_denied(message),
+            404 => Status::not_found(message),
+            409 => Status::failed_precondition(message),
+            429 => Status::resource_exhausted(message),
+            _ => Status::internal(message),
+        }
+    }
+}
+
+/// Who a call counts against for rate limiting; see `Proxies::client`.
+fn client<T>(req: &Request<T>, proxies: &Proxies) -> String {
+    let forwarded = req
+        .metadata()
+        .get_all(proxies.header.as_str())
+        .iter()
+        .filter_map(|value| value.to_str().ok());
+    proxies.client(req.remote_addr().map(|addr| addr.ip()), forwarded)
+}
+
+/// An account token sent as `authorization: Bearer <token>` metadata.
+fn bearer<T>(req: &Request<T>) -> Option<String> {
+    req.metadata()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_owned)
+}
+
+fn pid(pid: u64) -> ServerResult<esgea::PlayerId> {
+    usize::try_from(pid).map_err(|_| ServerError::BadId(pid.to_string()))
+}
+
+/// The gRPC message for one JSON push.
+fn message(json: &str) -> pb::Push {
+    let kind = match serde_json::from_str(json) {
+        Ok(Push::Observed(envelopes)) => pb::push::Kind::Observed(pb::Observed {
+            envelopes: envelopes
+                .into_iter()
+                .map(|e| pb::Envelope {
+                    turn: e.turn,
+                    phase: format!("{:?}", e.phase),
+                    seq: e.seq,
+                    public: e.public,
+                    count: e.count,
+                    cause: e.cause,
+                    observation_json: serde_json::to_string(&e.obs).unwrap_or_default(),
+                })
+                .collect(),
+        }),
+        _ => pb::push::Kind::NoticeJson(json.into()),
+    };
+    pb::Push { kind: Some(kind) }
+}
+
+pub struct Service {
+    state: Data<Mutex<State>>,
+    limits: Data<limit::Limits>,
+    proxies: Proxies,
+}
+
+#[tonic::async_trait]
+impl Esgea for Service {
+    async fn create_game(
+        &self,
+        req: Request<pb::CreateGameRequest>,
+    ) -> Result<Response<pb::Created>, Status> {
+        self.limits.lobby.spend(client(&req, &self.proxies))?;
+        let json = req.into_inner().options_json;
+        let options = if json.is_empty() {
+            GameOptions::default()
+        } else {
+            serde_json::from_str(&json).map_err(|e| ServerError::BadOptions(e.to_string()))?
+        };
+        let created = create_game(self.state.clone(), options)?;
+        Ok(Response::new(pb::Created {
+            gid: created.gid,
+            join_code: created.join_code,
+        }))
+    }
+
+    async fn join_game(
+        &self,
+        req: Request<pb::JoinGameRequest>,
+    ) -> Result<Response<pb::Joined>, Status> {
+        self.limits.lobby.spend(client(&req, &self.proxies))?;
+        let account_token = bearer(&req);
+        let req = req.into_inner();
+        let gid = parse_gid(&req.gid)?;
+        shared::pull(&self.state, gid).await;
+        // Joining with an account's token rates the game for it.
+        let (account, slot) = {
+            let st = self.state.lock();
+            let account = account_token.and_then(|token| st.ratings.account_for(&token));
+            (account, st.game(gid)?)
+        };
+        let joined = slot
+            .lock()
+            .join(req.code.as_deref(), req.team, account, |_| Ok(()))?;
+        Ok(Response::new(pb::Joined {
+            pid: joined.pid as u64,
+            token: joined.token,
+        }))
+    }
+
+    async fn do_action(
+        &self,
+        req: Request<pb::DoActionRequest>,
+    ) -> Result<Response<pb::ActionDone>, Status> {
+        let req = req.into_inner();
+        let (gid, pid) = (parse_gid(&req.gid)?, pid(req.pid)?);
+        let action = serde_json::from_str::<esgea::Action>(&req.action_json)
+            .map_err(|e| ServerError::BadAction(e.to_string()))?;
+        shared::pull(&self.state, gid).await;
+        let slot = self.state.lock().game(gid)?;
+        let mut gs = slot.lock();
+        gs.check_token(pid, Some(&req.token))?;
+        self.limits.actions.check_seat(gid, pid)?;
+        let id = gs.play(pid, action, req.key)?;
+        Ok(Response::new(pb::ActionDone { id }))
+    }
+
+    type EventsStream = Pin<Box<dyn Stream<Item = Result<pb::Push, Status>> + Send>>;
+
+    async fn events(
+        &self,
+        req: Request<pb::EventsRequest>,
+    ) -> Result<Response<Self::EventsStream>, Status> {
+        let req = req.into_inner();
+        let (gid, pid) = (parse_gid(&req.gid)?, pid(req.pid)?);
+        shared::pull(&self.state, gid).await;
+        let slot = self.state.lock().game(gid)?;
+        let mut gs = slot.lock();
+        gs.check_token(pid, Some(&req.token))?;
+        let (tx, rx) = mpsc::channel(BACKLOG);
+        gs.subscribe(pid, Subscriber::Channel(tx), req.since);
+        let stream = ReceiverStream::new(rx).map(|json| message(&json)).map(Ok);
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Serve the gRPC API on `addr` until the server stops, trusting `proxies` to say whom they
+/// forwarded calls for.
+pub async fn serve(
+    addr: SocketAddr,
+    state: Data<Mutex<State>>,
+    limits: Data<limit::Limits>,
+    proxies: Proxies,
+) -> Result<(), String> {
+    tracing::info!(%addr, "serving gRPC");
+    let service = Service {
+        state,
+        limits,
+        proxies,
+    };
+    tonic::transport::Server::builder()
+        .add_service(EsgeaServer::new(service))
+        .serve(addr)
+        .await
+        .map_err(|e| format!("serving gRPC on {addr}: {e}"))
+}
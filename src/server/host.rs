@@ -0,0 +1,264 @@
+use actix_session::SessionExt;
+use actix_web::web::{self, Data};
+use actix_web::{post, HttpRequest, HttpResponse};
+use esgea::protocol::Push;
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+use crate::error::{ServerError, ServerResult};
+use crate::push::{Seated, Subscriber};
+use crate::{
+    new_token, parse_gid, parse_ids, session_seats, shared, GameState, State, Status, SEATS,
+};
+
+impl GameState {
+    /// Check that `pid` hosts this game.
+    pub(crate) fn check_host(&self, pid: esgea::PlayerId) -> ServerResult<()> {
+        match self.host {
+            Some(host) if host == pid => Ok(()),
+            _ => Err(ServerError::NotHost),
+        }
+    }
+
+    /// Check that the game is still gathering players.
+    fn check_lobby(&self) -> ServerResult<()> {
+        match self.status {
+            Status::Lobby => Ok(()),
+            Status::Running | Status::Paused => Err(ServerError::AlreadyStarted),
+            Status::Finished | Status::Abandoned => Err(ServerError::GameOver),
+        }
+    }
+
+    /// Seats still in the game that people play, rather than bots.
+    pub(crate) fn humans(&self) -> Vec<esgea::PlayerId> {
+        let game = self.game.lock();
+        (0..self.tokens.len())
+            .filter(|seat| !self.bots.contains_key(seat) && !game.players[*seat].resigned)
+            .collect()
+    }
+
+    /// If the host has gone, make the first person still playing host and tell everyone.
+    fn pass_host(&mut self) {
+        if self.host.is_some() {
+            return;
+        }
+        self.host = self.humans().first().copied();
+        if let Some(host) = self.host {
+            let json = serde_json::to_string(&Push::Host(host)).unwrap_or_default();
+            for seat in 0..self.pid_channels.len() {
+                self.deliver(seat, &json);
+            }
+        }
+    }
+
+    /// Tell everyone which seats are ready, after any of that changes.
+    pub(crate) fn tell_ready(&mut self) {
+        self.tell_all(&Push::Ready(self.ready.clone()));
+    }
+
+    /// Take `pid` out of a game that hasn't begun. Later seats move down one and are told
+    /// their new id with `{"seat": {"pid"}}`; the board is set up afresh for those left, from
+    /// the same seed, and each seat is pushed its new setup. Sequence numbers carry on from
+    /// where they were, so clients' `since` cursors stay good.
+    pub(crate) fn unseat(&mut self, pid: esgea::PlayerId) {
+        let mut game = self.game.lock();
+        let mut fresh = esgea::Game::seeded(game.rules.clone(), game.seed);
+        fresh.cities = self.map.graph();
+        let seats: Vec<_> = (0..game.players.len())
+            .filter(|&seat| seat != pid)
+            .collect();
+        let next_seqs: Vec<u64> = seats
+            .iter()
+            .map(|seat| game.next_seq.get(seat).copied().unwrap_or_default())
+            .collect();
+        for (new, &seat) in seats.iter().enumerate() {
+            fresh.next_seq.insert(new, next_seqs[new]);
+            let at = self.map.start(new).unwrap_or_default();
+            fresh.spawn_player(at, game.profiles.get(&seat).cloned());
+        }
+        fresh.reset_event();
+        self.sent = next_seqs;
+        *game = fresh;
+        drop(game);
+
+        self.tokens.remove(pid);
+        self.teams.remove(pid);
+        self.accounts.remove(pid);
+        self.ready.remove(pid);
+        self.recent.remove(pid);
+        self.hooks.remove(pid);
+        self.pid_channels.remove(pid);
+        self.bots = std::mem::take(&mut self.bots)
+            .into_iter()
+            .filter(|&(seat, _)| seat != pid)
+            .map(|(seat, level)| (if seat > pid { seat - 1 } else { seat }, level))
+            .collect();
+        self.host = match self.host {
+            Some(host) if host == pid => None,
+            Some(host) if host > pid => Some(host - 1),
+            host => host,
+        };
+        self.pause_votes.clear();
+        self.end_votes.clear();
+        self.chat.unseat(pid);
+        self.renders.clear();
+        self.generation += 1;
+
+        for seat in pid..self.pid_channels.len() {
+            if let Some(sub) = &self.pid_channels[seat] {
+                sub.notice(&Push::Seat { pid: seat });
+                if let Subscriber::Ws(addr) = sub {
+                    addr.do_send(Seated(self.gid, seat));
+                }
+            }
+        }
+        self.tell_ready();
+        self.distribute_updates();
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ReadyQuery {
+    ready: Option<bool>,
+}
+
+/// Say the seat the request identifies is ready to play, or with `?ready=false` that it no
+/// longer is. Answers with every seat's flag, which everyone is also sent as `{"ready": [..]}`.
+#[post("/ready/{gid}")]
+pub async fn ready(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ReadyQuery>,
+) -> ServerResult<HttpResponse> {
+    let gid = parse_gid(&path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    let pid = gs.identify(gid, &req)?;
+    gs.check_lobby()?;
+    gs.ready[pid] = query.ready.unwrap_or(true);
+    gs.generation += 1;
+    gs.tell_ready();
+    shared::save(&gs);
+    Ok(HttpResponse::Ok().json(&gs.ready))
+}
+
+/// Start the game. Only the host may, once there are at least two seats and every one of them
+/// is ready.
+#[post("/begin/{gid}")]
+pub async fn begin(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> ServerResult<HttpResponse> {
+    let gid = parse_gid(&path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    let pid = gs.identify(gid, &req)?;
+    gs.check_host(pid)?;
+    gs.check_lobby()?;
+    let _game = gs.span().entered();
+    gs.start()?;
+    tracing::info!(pid, "host began the game");
+    Ok(HttpResponse::Ok().json(gs.status))
+}
+
+/// Remove seat `pid` from a game that hasn't begun; only the host may, and only seats that
+/// aren't ready. The kicked seat's stream is sent `{"kicked": true}` and hears nothing more.
+#[post("/kick/{gid}/{pid}")]
+pub async fn kick(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> ServerResult<HttpResponse> {
+    let (gid, pid) = parse_ids(path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    let host = gs.identify(gid, &req)?;
+    gs.check_host(host)?;
+    gs.check_lobby()?;
+    match gs.ready.get(pid) {
+        None => return Err(ServerError::BadId(pid.to_string())),
+        Some(_) if pid == host => {
+            return Err(ServerError::CantKick("you host this game".into()));
+        }
+        Some(true) => {
+            return Err(ServerError::CantKick(format!("seat {pid} is ready")));
+        }
+        Some(false) => {}
+    }
+    if let Some(sub) = gs.pid_channels[pid].take() {
+        sub.notice(&Push::Kicked(true));
+        sub.close();
+    }
+    let _game = gs.span().entered();
+    gs.unseat(pid);
+    tracing::info!(pid, "kicked from the lobby");
+    shared::save(&gs);
+    Ok(HttpResponse::Ok().body(()))
+}
+
+/// Give up seat `pid`. Before the game begins the seat is freed, and later seats move down one
+/// as with a kick. After, the player resigns and the seat's token stops working. A game left
+/// with fewer than two players, or only bots, ends; a host who leaves hands over to the next
+/// person still playing.
+#[post("/leave/{gid}/{pid}")]
+pub async fn leave(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> ServerResult<HttpResponse> {
+    let (gid, pid) = parse_ids(path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    gs.authorize(gid, pid, &req)?;
+    let _game = gs.span().entered();
+    match gs.status {
+        Status::Lobby => {
+            if let Some(sub) = gs.pid_channels[pid].take() {
+                sub.close();
+            }
+            gs.unseat(pid);
+            if gs.humans().is_empty() {
+                gs.status = Status::Abandoned;
+            }
+        }
+        Status::Running | Status::Paused => {
+            gs.game.lock().resign(pid)?;
+            gs.tokens[pid] = new_token();
+            gs.hooks[pid] = None;
+            if let Some(sub) = gs.pid_channels[pid].take() {
+                sub.close();
+            }
+            gs.announce_presence(pid, false);
+            if gs.host == Some(pid) {
+                gs.host = None;
+            }
+            let players = gs.game.lock().turn_order().count();
+            if players < 2 || gs.humans().is_empty() {
+                gs.status = Status::Finished;
+            }
+            gs.touch();
+            gs.distribute_updates();
+        }
+        Status::Finished | Status::Abandoned => return Err(ServerError::GameOver),
+    }
+    tracing::info!(pid, "player left");
+    gs.pass_host();
+    shared::save(&gs);
+    let status = gs.status;
+    drop(gs);
+
+    let seats: Vec<_> = session_seats(&req)
+        .into_iter()
+        .filter(|seat| seat.gid != gid.to_string() || seat.pid != pid)
+        .collect();
+    if let Err(e) = req.get_session().insert(SEATS, seats) {
+        tracing::warn!(error = %e, "couldn't forget seat in session");
+    }
+    Ok(HttpResponse::Ok().json(status))
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use actix_web::HttpRequest;
+use parking_lot::Mutex;
+
+use crate::config::Proxies;
+use crate::error::{ServerError, ServerResult};
+
+/// Forget clients once this many are tracked, keeping only those still being throttled.
+const MAX_CLIENTS: usize = 10_000;
+
+/// Token buckets per client: a client may make `burst` requests at once, refilled at `per_sec`.
+pub struct RateLimiter {
+    burst: f64,
+    per_sec: f64,
+    buckets: Mutex<HashMap<String, (f64, Instant)>>,
+    proxies: Arc<Proxies>,
+}
+
+impl RateLimiter {
+    pub fn new(burst: u32, per_sec: f64, proxies: Arc<Proxies>) -> Self {
+        RateLimiter {
+            burst: burst as f64,
+            per_sec,
+            buckets: Mutex::new(HashMap::new()),
+            proxies,
+        }
+    }
+
+    /// Spend one request for the client that sent `req`, or fail with `RateLimited`.
+    pub fn check(&self, req: &HttpRequest) -> ServerResult<()> {
+        let forwarded = req
+            .headers()
+            .get_all(self.proxies.header.as_str())
+            .filter_map(|value| value.to_str().ok());
+        let peer = req.peer_addr().map(|addr| addr.ip());
+        self.spend(self.proxies.client(peer, forwarded))
+    }
+
+    /// Spend one request for seat `pid` of `gid`, once the request has proven it holds it.
+    pub fn check_seat(&self, gid: u128, pid: esgea::PlayerId) -> ServerResult<()> {
+        self.spend(format!("{gid}/{pid}"))
+    }
+
+    /// Spend one request for `client`, or fail with `RateLimited`.
+    pub fn spend(&self, client: String) -> ServerResult<()> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+        if buckets.len() >= MAX_CLIENTS {
+            buckets.retain(|_, (tokens, at)| {
+                *tokens + now.duration_since(*at).as_secs_f64() * self.per_sec < self.burst
+            });
+        }
+        let (tokens, at) = buckets.entry(client).or_insert((self.burst, now));
+        *tokens = (*tokens + now.duration_since(*at).as_secs_f64() * self.per_sec).min(self.burst);
+        *at = now;
+        if *tokens < 1.0 {
+            return Err(ServerError::RateLimited);
+        }
+        *tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// Rate limits for the endpoints that take the game lock or create state.
+pub struct Limits {
+    pub actions: RateLimiter,
+    /// Creating and joining games.
+    pub lobby: RateLimiter,
+}
+
+impl Limits {
+    pub fn new(proxies: Proxies) -> Self {
+        let proxies = Arc::new(proxies);
+        Limits {
+            actions: RateLimiter::new(10, 2.0, proxies.clone()),
+            lobby: RateLimiter::new(5, 0.2, proxies),
+        }
+    }
+}
+
+impl Proxies {
+    /// Who a request from `peer` counts against, given the `forwarded` header values it came
+    /// with. Tokens aren't used, since a client could send a fresh made-up one each time. A
+    /// request from a trusted proxy counts against the last address in the header that isn't
+    /// itself a trusted proxy; anything else counts against its peer address, since a client
+    /// can set the header to anything.
+    pub fn client<'a>(
+        &self,
+        peer: Option<IpAddr>,
+        forwarded: impl Iterator<Item = &'a str>,
+    ) -> String {
+        let Some(mut client) = peer else {
+            return "unknown".into();
+        };
+        if !self.trusted.contains(&client) {
+            return client.to_string();
+        }
+        let hops: Vec<&str> = forwarded.flat_map(|value| value.split(',')).collect();
+        for hop in hops.into_iter().rev() {
+            let Ok(hop) = hop.trim().parse() else { break };
+            client = hop;
+            if !self.trusted.contains(&client) {
+                break;
+            }
+        }
+        client.to_string()
+    }
+}
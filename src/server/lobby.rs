@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Status;
+
+/// Options for `POST /start_game`. Every field is optional; an empty body makes a public,
+/// unnamed game on the classic map.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GameOptions {
+    pub name: String,
+    /// Capped at the number of start positions the map has.
+    pub max_players: Option<usize>,
+    /// A built-in map name; see `esgea::Map::named`.
+    pub map: String,
+    /// Private games are left out of `/lobby` and need the join code to enter.
+    pub public: bool,
+    /// Seconds a player may take over a turn before it is skipped; unlimited if unset.
+    pub turn_seconds: Option<u64>,
+    /// The server's configured rules if unset.
+    pub rules: Option<RulesChoice>,
+}
+
+/// Rules for a new game: a preset's name (see `esgea::Ruleset::named`) or a full ruleset.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum RulesChoice {
+    Preset(String),
+    Custom(esgea::Ruleset),
+}
+
+impl Default for GameOptions {
+    fn default() -> Self {
+        GameOptions {
+            name: String::new(),
+            max_players: None,
+            map: "classic".into(),
+            public: true,
+            turn_seconds: None,
+            rules: None,
+        }
+    }
+}
+
+/// What `/lobby` shows about a game, without revealing anything about its state.
+#[derive(Debug, Clone, Serialize)]
+pub struct LobbyEntry {
+    pub gid: String,
+    pub name: String,
+    pub map: String,
+    pub players: usize,
+    pub max_players: usize,
+    pub status: Status,
+    pub turn_seconds: Option<u64>,
+    /// The preset the game was created with, if it was picked by name.
+    pub preset: Option<String>,
+    pub rules: esgea::Ruleset,
+}
+
+/// A short code for sharing a private game.
+pub fn join_code() -> String {
+    use rand::Rng;
+    const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    let mut rng = rand::thread_rng();
+    (0..6)
+        .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+        .collect()
+}
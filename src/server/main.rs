@@ -0,0 +1,1053 @@
+use actix_session::{storage::CookieSessionStore, SessionExt, SessionMiddleware};
+use actix_web::cookie::SameSite;
+use actix_web::web::{Bytes, Data};
+use actix_web::{
+    get,
+    dev::Service, http::header::{self, ContentType}, web, App, HttpResponse, HttpServer,
+    Responder,
+};
+use actix_web::{delete, post};
+use actix_web::{Error, HttpRequest};
+use actix_web_actors::ws;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod assets;
+mod bots;
+mod chat;
+mod clock;
+mod config;
+mod error;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod host;
+mod limit;
+mod lobby;
+mod notify;
+mod pause;
+mod push;
+mod queue;
+mod ratings;
+mod shared;
+mod stats;
+mod tournament;
+
+use error::{ServerError, ServerResult};
+use esgea::protocol::{Created, Joined, Push, PROTOCOL_VERSION};
+use lobby::{GameOptions, LobbyEntry, RulesChoice};
+use push::{ReceiverStream, Subscriber};
+
+/// Games idle this long are abandoned.
+const IDLE_TTL: Duration = Duration::from_secs(30 * 60);
+/// Paused games are waiting on real life, so they're given much longer.
+const PAUSED_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Finished and abandoned games are kept this long for late viewers, then dropped.
+const RETAIN_TTL: Duration = Duration::from_secs(10 * 60);
+pub(crate) const REAP_INTERVAL: Duration = Duration::from_secs(60);
+/// Replays of dropped games kept for download, oldest forgotten first.
+const MAX_REPLAYS: usize = 1000;
+/// Client action ids remembered per seat, so a resent action isn't played twice.
+const RECENT_ACTIONS: usize = 64;
+/// Longest client action id accepted.
+const MAX_ACTION_KEY: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    /// Waiting for enough players to start.
+    Lobby,
+    Running,
+    /// Running, but frozen until resumed; see `pause`.
+    Paused,
+    Finished,
+    /// Nobody touched the game for `IDLE_TTL`, or `PAUSED_TTL` while paused.
+    Abandoned,
+}
+
+struct GameState {
+    gid: u128,
+    game: Arc<Mutex<esgea::Game>>,
+    name: String,
+    map: esgea::Map,
+    /// Name of the rules preset the game was created with, if any.
+    preset: Option<String>,
+    max_players: usize,
+    /// Set for private games, which can only be joined with it.
+    join_code: Option<String>,
+    status: Status,
+    /// How long a player may take over a turn before it is skipped.
+    turn_limit: Option<Duration>,
+    /// When the current turn will be skipped.
+    deadline: Option<Instant>,
+    /// What was left on the turn clock when the game was paused.
+    paused_left: Option<Duration>,
+    /// Seats that have asked to pause or resume, whichever the game isn't.
+    pause_votes: BTreeSet<esgea::PlayerId>,
+    /// Seats that have asked to end the game; see `end_game`.
+    end_votes: BTreeSet<esgea::PlayerId>,
+    /// The seat that created the game by joining it first. It decides when the game begins,
+    /// may kick seats before then, and may pause and resume alone.
+    host: Option<esgea::PlayerId>,
+    /// Turn the clock was last wound for.
+    clock_turn: u32,
+    /// Last time anyone joined or acted.
+    touched: Instant,
+    pid_channels: Vec<Option<Subscriber>>,
+    /// Sequence number of the next observation to push to each seat.
+    sent: Vec<u64>,
+    /// Secret handed to each seat on join; acting or observing as that seat requires it.
+    tokens: Vec<String>,
+    /// Team each seat joined with, if any; shares a team chat channel.
+    teams: Vec<Option<u32>>,
+    /// Each seat has said it's ready to play; see `host`.
+    ready: Vec<bool>,
+    /// Account each seat joined with, if any; see `ratings`.
+    accounts: Vec<Option<String>>,
+    /// Each seat's latest client action ids and the action each was played as, newest last.
+    recent: Vec<VecDeque<(String, esgea::ActionId)>>,
+    /// Where to ping each seat when its turn comes, if it asked.
+    hooks: Vec<Option<notify::Hook>>,
+    /// The result has been counted toward ratings.
+    rated: bool,
+    /// Seats played by the server, and how well.
+    bots: BTreeMap<esgea::PlayerId, esgea::BotLevel>,
+    /// A task is playing the bots' turns; see `bots::spawn`.
+    bot_driver: bool,
+    chat: chat::ChatLog,
+    /// Bumped on every change to the game, so anything derived from it can tell it's stale.
+    generation: u64,
+    /// Rendered boards by perspective, with the generation they were drawn at.
+    renders: BTreeMap<esgea::PlayerId, (u64, Arc<str>)>,
+}
+
+impl GameState {
+    /// A game in the lobby, played by `ruleset` unless `options` pick other rules.
+    fn new(gid: u128, options: GameOptions, ruleset: esgea::Ruleset) -> ServerResult<Self> {
+        let map = esgea::Map::named(&options.map).ok_or(ServerError::NoSuchMap(options.map))?;
+        let (preset, ruleset) = match options.rules {
+            None => (None, ruleset),
+            Some(RulesChoice::Custom(rules)) => {
+                rules.check().map_err(ServerError::BadOptions)?;
+                (None, rules)
+            }
+            Some(RulesChoice::Preset(name)) => match esgea::Ruleset::named(&name) {
+                Some(rules) => (Some(name), rules),
+                None => return Err(ServerError::NoSuchRuleset(name)),
+            },
+        };
+        let max_players = options
+            .max_players
+            .map_or(map.max_players(), |n| n.min(map.max_players()));
+        Ok(Self {
+            gid,
+            game: Arc::new(Mutex::new(esgea::Game::on_map(&map, ruleset))),
+            name: options.name,
+            map,
+            preset,
+            max_players,
+            join_code: (!options.public).then(lobby::join_code),
+            turn_limit: options.turn_seconds.map(Duration::from_secs),
+            deadline: None,
+            paused_left: None,
+            pause_votes: BTreeSet::new(),
+            end_votes: BTreeSet::new(),
+            host: None,
+            clock_turn: 0,
+            status: Status::Lobby,
+            touched: Instant::now(),
+            pid_channels: vec![],
+            sent: vec![],
+            tokens: vec![],
+            teams: vec![],
+            ready: vec![],
+            accounts: vec![],
+            recent: vec![],
+            hooks: vec![],
+            rated: false,
+            bots: BTreeMap::new(),
+            bot_driver: false,
+            chat: chat::ChatLog::default(),
+            generation: 0,
+            renders: BTreeMap::new(),
+        })
+    }
+
+    fn summary(&self, gid: u128) -> LobbyEntry {
+        LobbyEntry {
+            gid: gid.to_string(),
+            name: self.name.clone(),
+            map: self.map.name.clone(),
+            players: self.tokens.len(),
+            max_players: self.max_players,
+            status: self.status,
+            turn_seconds: self.turn_limit.map(|limit| limit.as_secs()),
+            preset: self.preset.clone(),
+            rules: self.game.lock().rules.clone(),
+        }
+    }
+
+    /// A span for work on this game, so its events can be picked out of interleaved logs.
+    fn span(&self) -> tracing::Span {
+        tracing::info_span!("game", gid = %self.gid)
+    }
+
+    /// Check that someone may still take a seat.
+    fn open_seat(&self) -> ServerResult<()> {
+        match self.status {
+            Status::Lobby => {}
+            Status::Running | Status::Paused => return Err(ServerError::AlreadyStarted),
+            Status::Finished | Status::Abandoned => return Err(ServerError::GameOver),
+        }
+        if self.tokens.len() >= self.max_players {
+            return Err(ServerError::GameFull)
+        }
+        Ok(())
+    }
+
+    /// Add a player at the map's next starting location, returning their id and seat token.
+    /// Check `open_seat` first; past the map's last start the player lands on location 0.
+    fn seat(&mut self, team: Option<u32>) -> (esgea::PlayerId, String) {
+        self.seat_with(team, new_token())
+    }
+
+    /// `seat`, with a token chosen beforehand.
+    fn seat_with(&mut self, team: Option<u32>, token: String) -> (esgea::PlayerId, String) {
+        self.touched = Instant::now();
+        self.generation += 1;
+        self.pid_channels.push(None);
+        self.sent.push(0);
+        self.teams.push(team);
+        self.accounts.push(None);
+        self.ready.push(false);
+        self.recent.push(VecDeque::new());
+        self.hooks.push(None);
+        self.tokens.push(token.clone());
+        let mut gm = self.game.lock();
+        let at = self.map.start(gm.players.len()).unwrap_or_default();
+        let pid = gm.spawn_player(at, None);
+        (pid, token)
+    }
+
+    /// The action `pid` already played for client action id `key`, if it's a resend.
+    fn replayed(&self, pid: esgea::PlayerId, key: &str) -> Option<esgea::ActionId> {
+        self.recent[pid]
+            .iter()
+            .find(|(seen, _)| seen == key)
+            .map(|&(_, id)| id)
+    }
+
+    /// Remember that `pid`'s client action id `key` was played as `id`.
+    fn remember(&mut self, pid: esgea::PlayerId, key: String, id: esgea::ActionId) {
+        let recent = &mut self.recent[pid];
+        if recent.len() >= RECENT_ACTIONS {
+            recent.pop_front();
+        }
+        recent.push_back((key, id));
+    }
+
+    /// Begin the first turn and tell everyone, once there are at least two seats and every one
+    /// of them is ready. Nobody's turn or income starts before then.
+    fn start(&mut self) -> ServerResult<()> {
+        if self.tokens.len() < 2 {
+            return Err(ServerError::NotReady("a game needs at least two players".into()))
+        }
+        let unready: Vec<_> = (0..self.ready.len()).filter(|&seat| !self.ready[seat]).collect();
+        if !unready.is_empty() {
+            return Err(ServerError::NotReady(format!("seats {unready:?} aren't ready")))
+        }
+        self.game.lock().begin()?;
+        self.generation += 1;
+        self.status = Status::Running;
+        self.wind_clock();
+        self.distribute_updates();
+        Ok(())
+    }
+
+    /// How long the game may go untouched before it's abandoned.
+    fn idle_ttl(&self) -> Duration {
+        match self.status {
+            Status::Paused => PAUSED_TTL,
+            _ => IDLE_TTL,
+        }
+    }
+
+    /// Note activity, and move to `Finished` once the engine has a winner.
+    fn touch(&mut self) {
+        self.touched = Instant::now();
+        self.generation += 1;
+        self.renders.clear();
+        if self.status == Status::Running && self.game.lock().winner().is_some() {
+            self.status = Status::Finished;
+        }
+        self.wind_clock();
+    }
+
+    /// Deliver `pid`'s updates to `sub` from now on, replacing any stream they had open. It
+    /// starts with the chat so far, who else is connected, and, given the sequence number of
+    /// the first observation the client hasn't seen, everything it missed. The others are
+    /// told `pid` is here.
+    fn subscribe(&mut self, pid: esgea::PlayerId, sub: Subscriber, since: Option<u64>) {
+        if pid < self.pid_channels.len() {
+            sub.notice(&Push::Hello { version: PROTOCOL_VERSION });
+            sub.notice(&Push::Chat(self.chat_for(pid)));
+            let present = (0..self.pid_channels.len())
+                .filter(|&other| other != pid && self.pid_channels[other].is_some())
+                .collect();
+            sub.notice(&Push::Present(present));
+            if let Some(seq) = since {
+                let game = self.game.lock();
+                let missed = game.history.since(pid, seq);
+                if !missed.is_empty() {
+                    sub.notice(&Push::Observed(missed.to_vec()));
+                }
+            }
+            if let Subscriber::Ws(addr) = &sub {
+                addr.do_send(push::Seated(self.gid, pid));
+            }
+            if let Some(old) = self.pid_channels[pid].replace(sub) {
+                old.close();
+            }
+            self.announce_presence(pid, true);
+        }
+    }
+
+    fn replay(&self) -> Arc<esgea::Replay> {
+        Arc::new(self.game.lock().replay(&self.map))
+    }
+
+    /// The board as `pid` sees it, drawn at most once per generation.
+    fn render(&mut self, pid: esgea::PlayerId) -> Arc<str> {
+        match self.renders.get(&pid) {
+            Some((generation, svg)) if *generation == self.generation => svg.clone(),
+            _ => {
+                let svg: Arc<str> = self.game.lock().render_svg(pid).into();
+                self.renders.insert(pid, (self.generation, svg.clone()));
+                svg
+            }
+        }
+    }
+
+    /// Check that `req` carries the token of seat `pid` in game `gid`.
+    fn authorize(&self, gid: u128, pid: esgea::PlayerId, req: &HttpRequest) -> ServerResult<()> {
+        self.check_token(pid, presented_token(gid, pid, req).as_deref())
+    }
+
+    /// Check that `presented` is seat `pid`'s token.
+    fn check_token(&self, pid: esgea::PlayerId, presented: Option<&str>) -> ServerResult<()> {
+        match (self.tokens.get(pid), presented) {
+            (Some(token), Some(presented)) if token == presented => Ok(()),
+            _ => Err(ServerError::NotYourSeat),
+        }
+    }
+
+    /// Seat a new player, with `code` if the game is private and on `team` if any, for
+    /// `account` if it's rated. `remember` is shown the seat before it's taken, and can still
+    /// refuse it.
+    fn join(
+        &mut self,
+        code: Option<&str>,
+        team: Option<u32>,
+        account: Option<String>,
+        remember: impl FnOnce(&Joined) -> ServerResult<()>,
+    ) -> ServerResult<Joined> {
+        self.open_seat()?;
+        if self.join_code.is_some() && self.join_code.as_deref() != code {
+            return Err(ServerError::BadJoinCode)
+        }
+        let _game = self.span().entered();
+        let next = Joined { pid: self.tokens.len(), token: new_token() };
+        remember(&next)?;
+        let (pid, token) = self.seat_with(team, next.token);
+        tracing::info!(pid, ?team, rated = account.is_some(), "player joined");
+        if pid == 0 {
+            self.host = Some(pid);
+        }
+        self.accounts[pid] = account;
+        self.tell_ready();
+        shared::save(self);
+        Ok(Joined { pid, token })
+    }
+
+    /// Play `action` as `pid`, who has already been authorized, unless `key` shows it's a
+    /// resend of one already played.
+    fn play(
+        &mut self,
+        pid: esgea::PlayerId,
+        action: esgea::Action,
+        key: Option<String>,
+    ) -> ServerResult<esgea::ActionId> {
+        if key.as_ref().is_some_and(|key| key.len() > MAX_ACTION_KEY) {
+            return Err(ServerError::BadAction(format!("key longer than {MAX_ACTION_KEY} bytes")))
+        }
+        let _game = self.span().entered();
+        if let Some(id) = key.as_deref().and_then(|key| self.replayed(pid, key)) {
+            tracing::debug!(pid, id, "resent action ignored");
+            return Ok(id)
+        }
+        if self.status != Status::Running {
+            return Err(ServerError::NotRunning)
+        }
+        let id = match self.game.lock().do_action(pid, action.clone()) {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::debug!(pid, ?action, error = ?e, "action rejected");
+                return Err(e.into())
+            }
+        };
+        tracing::info!(pid, ?action, id = ?id, "action");
+        stats::acted();
+        if let Some(key) = key {
+            self.remember(pid, key, id);
+        }
+        self.touch();
+        self.distribute_updates();
+        Ok(id)
+    }
+
+    /// The seat whose token `req` carries, for endpoints that don't name one.
+    fn identify(&self, gid: u128, req: &HttpRequest) -> ServerResult<esgea::PlayerId> {
+        let session = session_seats(req)
+            .into_iter()
+            .filter(|seat| seat.gid == gid.to_string())
+            .map(|seat| seat.token);
+        request_token(req)
+            .into_iter()
+            .chain(session)
+            .find_map(|token| self.tokens.iter().position(|t| *t == token))
+            .ok_or(ServerError::NotYourSeat)
+    }
+
+    /// Push `json` to `pid`'s event stream, or to the replica holding it if it isn't here.
+    fn deliver(&mut self, pid: esgea::PlayerId, json: &str) {
+        match &self.pid_channels[pid] {
+            Some(tx) => {
+                if !tx.push(json) {
+                    tracing::warn!(gid = %self.gid, pid, "event stream gone, dropping delivery");
+                    if let Some(sub) = self.pid_channels[pid].take() {
+                        sub.close();
+                    }
+                    self.announce_presence(pid, false);
+                }
+            }
+            None => shared::forward(self.gid, pid, json),
+        }
+    }
+
+    /// Push everything each seat has observed since the last call (including "your turn"
+    /// notices) to its event stream, straight from the game's history, so pushes carry the same
+    /// sequence numbers `/events/{gid}/{pid}/since/{seq}` does.
+    fn distribute_updates(&mut self) {
+        let game = self.game.clone();
+        let mut game = game.lock();
+        for pid in 0..self.sent.len() {
+            let upds = game.history.since(pid, self.sent[pid]);
+            let Some(last) = upds.last() else { continue };
+            self.sent[pid] = last.seq + 1;
+            self.notify_turn(pid, upds);
+            let json = serde_json::to_string(&Push::Observed(upds.to_vec())).unwrap_or_default();
+            self.deliver(pid, &json);
+        }
+        // Everything is in the history; the per-action buffer would only grow.
+        game.reset_event();
+        drop(game);
+        shared::save(self);
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: String,
+}
+
+/// A seat this browser session holds, remembered in the session cookie on join.
+#[derive(Clone, Serialize, Deserialize)]
+struct Seat {
+    gid: String,
+    pid: esgea::PlayerId,
+    token: String,
+}
+
+const SEATS: &str = "seats";
+/// Seats a session remembers at most; joining another forgets the oldest, so the cookie stays
+/// under browsers' size limit.
+const MAX_SESSION_SEATS: usize = 16;
+
+fn session_seats(req: &HttpRequest) -> Vec<Seat> {
+    req.get_session()
+        .get::<Vec<Seat>>(SEATS)
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// A token sent explicitly with a request, as `Authorization: Bearer <token>` or a `?token=`
+/// query parameter (browsers can't set headers on a websocket upgrade).
+fn request_token(req: &HttpRequest) -> Option<String> {
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_owned);
+    bearer.or_else(|| {
+        web::Query::<TokenQuery>::from_query(req.query_string())
+            .ok()
+            .map(|q| q.into_inner().token)
+    })
+}
+
+/// The token presented for seat `pid`: sent with the request, or else from the session.
+fn presented_token(gid: u128, pid: esgea::PlayerId, req: &HttpRequest) -> Option<String> {
+    request_token(req)
+        .or_else(|| {
+            session_seats(req)
+                .into_iter()
+                .find(|seat| seat.gid == gid.to_string() && seat.pid == pid)
+                .map(|seat| seat.token)
+        })
+}
+
+/// The seats this session holds, as `[{gid, pid}]`.
+#[get("/whoami")]
+async fn whoami(req: HttpRequest) -> impl Responder {
+    HttpResponse::Ok().json(
+        session_seats(&req)
+            .into_iter()
+            .map(|seat| serde_json::json!({ "gid": seat.gid, "pid": seat.pid }))
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn new_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Everything the server knows. Each game has a lock of its own, so a slow request for one
+/// game doesn't hold up the others. Never lock this while holding a game's lock, and prefer
+/// letting go of it before taking one.
+struct State {
+    games: BTreeMap<u128, Arc<Mutex<GameState>>>,
+    queue: queue::Queue,
+    /// Rules new games are played by.
+    ruleset: esgea::Ruleset,
+    tournaments: BTreeMap<u128, tournament::Tournament>,
+    ratings: ratings::Ratings,
+    /// Replays of finished games that have since been dropped, newest last.
+    replays: VecDeque<(u128, Arc<esgea::Replay>)>,
+}
+
+fn parse_gid(gid: &str) -> ServerResult<u128> {
+    gid.parse().map_err(|_| ServerError::BadId(gid.into()))
+}
+
+fn parse_ids((gid, pid): (String, String)) -> ServerResult<(u128, esgea::PlayerId)> {
+    let pid = pid.parse().map_err(|_| ServerError::BadId(pid))?;
+    Ok((parse_gid(&gid)?, pid))
+}
+
+impl State {
+    /// Game `gid`, to lock once the state lock has been let go.
+    fn game(&self, gid: u128) -> ServerResult<Arc<Mutex<GameState>>> {
+        self.games.get(&gid).cloned().ok_or(ServerError::NoSuchGame)
+    }
+
+    /// Abandon idle games and drop those that have been over for a while, keeping the
+    /// replays of finished ones.
+    fn reap(&mut self) {
+        self.queue.expire();
+        let now = Instant::now();
+        self.reap_tournaments(now);
+        let replays = &mut self.replays;
+        self.games.retain(|gid, gs| {
+            let mut gs = gs.lock();
+            let idle = now.duration_since(gs.touched);
+            let ttl = gs.idle_ttl();
+            match gs.status {
+                Status::Lobby | Status::Running | Status::Paused if idle > ttl => {
+                    tracing::info!(gid = %gid, "abandoning idle game");
+                    gs.status = Status::Abandoned;
+                    gs.touched = now;
+                    true
+                }
+                Status::Finished | Status::Abandoned if idle > RETAIN_TTL => {
+                    if gs.status == Status::Finished {
+                        replays.push_back((*gid, gs.replay()));
+                        if replays.len() > MAX_REPLAYS {
+                            replays.pop_front();
+                        }
+                    }
+                    // Only our copy: another replica may still be using the shared one, which
+                    // expires by itself once no one saves it.
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+}
+
+/// Create a game from optional JSON `GameOptions`, returning `{gid, join_code}`. `rules` may be
+/// a preset name such as `"fog"` or a full `esgea::Ruleset`.
+#[post("/start_game")]
+async fn start_game(
+    state: Data<Mutex<State>>,
+    limits: Data<limit::Limits>,
+    req: HttpRequest,
+    body: Bytes,
+) -> ServerResult<HttpResponse> {
+    limits.lobby.check(&req)?;
+    let options = if body.is_empty() {
+        GameOptions::default()
+    } else {
+        serde_json::from_slice(&body).map_err(|e| ServerError::BadOptions(e.to_string()))?
+    };
+    Ok(HttpResponse::Ok().json(create_game(state, options)?))
+}
+
+/// Open a lobby for a new game played with `options`.
+fn create_game(state: Data<Mutex<State>>, options: GameOptions) -> ServerResult<Created> {
+    let gid: u128 = rand::random();
+    let ruleset = state.lock().ruleset.clone();
+    let gs = GameState::new(gid, options, ruleset)?;
+    stats::game_created();
+    let join_code = gs.join_code.clone();
+    let timed = gs.turn_limit.is_some();
+    shared::save(&gs);
+    state.lock().games.insert(gid, Arc::new(Mutex::new(gs)));
+    if timed {
+        clock::spawn(state, gid);
+    }
+    Ok(Created { gid: gid.to_string(), join_code })
+}
+
+#[derive(Deserialize)]
+struct LobbyQuery {
+    state: Option<Status>,
+}
+
+/// Public games, optionally only those in `?state=lobby|running|finished|abandoned`.
+#[get("/lobby")]
+async fn list_games(state: Data<Mutex<State>>, query: web::Query<LobbyQuery>) -> impl Responder {
+    shared::pull_all(&state).await;
+    let games: Vec<_> = state.lock().games.values().cloned().collect();
+    HttpResponse::Ok().append_header(ContentType::json()).json(
+        games
+            .iter()
+            .map(|gm| gm.lock())
+            .filter(|gm| gm.join_code.is_none())
+            .filter(|gm| query.state.is_none_or(|s| s == gm.status))
+            .map(|gm| gm.summary(gm.gid))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Declare a game over. The host ends it at once; otherwise it waits until every human seat
+/// has asked, and everyone is sent `{"asked": {by, to: "end"}}` for each request. Answers
+/// with the game's status.
+#[post("/end_game/{gid}/{pid}")]
+async fn end_game(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> ServerResult<HttpResponse> {
+    let (gid, pid) = parse_ids(path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    gs.authorize(gid, pid, &req)?;
+    gs.vote_end(pid);
+    shared::save(&gs);
+    Ok(HttpResponse::Ok().json(gs.status))
+}
+
+/// Remove a game that isn't running outright, closing its event streams; only the host may.
+#[delete("/game/{gid}/{pid}")]
+async fn delete_game(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> ServerResult<HttpResponse> {
+    let (gid, pid) = parse_ids(path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    {
+        let gs = slot.lock();
+        gs.authorize(gid, pid, &req)?;
+        gs.check_host(pid)?;
+        if matches!(gs.status, Status::Running | Status::Paused) {
+            return Err(ServerError::NotOver)
+        }
+    }
+    state.lock().games.remove(&gid);
+    shared::remove(gid);
+    Ok(HttpResponse::Ok().body(()))
+}
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    /// Sequence number of the first observation the client hasn't seen, when reconnecting.
+    since: Option<u64>,
+}
+
+/// `pid`'s observations as they happen, over a websocket. Reconnecting with `?since=<seq>`
+/// first replays whatever was missed while disconnected.
+#[get("/events/{gid}/{pid}")]
+async fn event_stream(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<StreamQuery>,
+    stream: web::Payload,
+) -> Result<HttpResponse, Error> {
+    let (gid, pid) = parse_ids(path.into_inner())?;
+    let actor = ReceiverStream::new(state.clone());
+    let mut res = ws::handshake(&req)?;
+
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    let _game = gs.span().entered();
+    tracing::debug!(pid, "opening event stream");
+    gs.authorize(gid, pid, &req)?;
+    let (addr, stream) = ws::WebsocketContext::create_with_addr(actor, stream);
+    gs.subscribe(pid, Subscriber::Ws(addr), query.since);
+
+    Ok(res.streaming(stream))
+}
+
+/// The same stream as `/events/{gid}/{pid}`, as Server-Sent Events: one JSON message per
+/// `data:` line, for clients that can't hold a websocket. Takes `?since=` the same way.
+#[get("/sse/{gid}/{pid}")]
+async fn sse_stream(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<StreamQuery>,
+) -> ServerResult<HttpResponse> {
+    let (gid, pid) = parse_ids(path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    gs.authorize(gid, pid, &req)?;
+    let (sub, response) = push::sse();
+    gs.subscribe(pid, sub, query.since);
+    Ok(response)
+}
+
+/// Every observation `pid` received from sequence number `seq` (the first one the client
+/// hasn't seen) onward, for catching up after a dropped event stream.
+#[get("/events/{gid}/{pid}/since/{seq}")]
+async fn events_since(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<(String, String, String)>,
+) -> ServerResult<HttpResponse> {
+    let (gid, pid, seq) = path.into_inner();
+    let (gid, pid) = parse_ids((gid, pid))?;
+    let seq: u64 = seq.parse().map_err(|_| ServerError::BadId(seq))?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let gs = slot.lock();
+    gs.authorize(gid, pid, &req)?;
+    let game = gs.game.lock();
+    Ok(HttpResponse::Ok().json(game.history.since(pid, seq)))
+}
+
+/// The board as seat `pid` knows it: an `esgea::GameView` with everything hidden from them
+/// left out.
+#[get("/state/{gid}/{pid}")]
+async fn player_state(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> ServerResult<HttpResponse> {
+    let (gid, pid) = parse_ids(path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let gs = slot.lock();
+    gs.authorize(gid, pid, &req)?;
+    let view = gs.game.lock().view_for(pid)?;
+    Ok(HttpResponse::Ok().json(view))
+}
+
+#[derive(Deserialize)]
+struct JoinQuery {
+    code: Option<String>,
+    team: Option<u32>,
+}
+
+/// Take the next seat at the map's next starting location; private games need
+/// `?code=<join code>`, and `?team=` joins a team chat channel. Joins are refused once the game
+/// is full or has started. The first to join hosts the game, and begins it with `/begin`.
+#[post("/join_game/{gid}")]
+async fn join_game(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<JoinQuery>,
+    limits: Data<limit::Limits>,
+) -> ServerResult<HttpResponse> {
+    limits.lobby.check(&req)?;
+    let gid = parse_gid(&path.into_inner())?;
+    shared::pull(&state, gid).await;
+    // Joining with an account's token rates the game for it. Seats in games that are gone
+    // needn't be remembered any longer.
+    let (account, slot, mut seats) = {
+        let st = state.lock();
+        let account = request_token(&req).and_then(|token| st.ratings.account_for(&token));
+        let seats: Vec<_> = session_seats(&req)
+            .into_iter()
+            .filter(|seat| seat.gid.parse().is_ok_and(|gid| st.games.contains_key(&gid)))
+            .collect();
+        (account, st.game(gid)?, seats)
+    };
+    let joined = slot.lock().join(query.code.as_deref(), query.team, account, |next| {
+        seats.push(Seat {
+            gid: gid.to_string(),
+            pid: next.pid,
+            token: next.token.clone(),
+        });
+        let stale = seats.len().saturating_sub(MAX_SESSION_SEATS);
+        seats.drain(..stale);
+        req.get_session().insert(SEATS, seats).map_err(|e| {
+            tracing::warn!(error = %e, "couldn't remember seat in session");
+            ServerError::Internal("couldn't remember the seat".into())
+        })
+    })?;
+    Ok(HttpResponse::Ok().json(joined))
+}
+
+#[get("/render/{gid}/{pid}")]
+async fn render(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> ServerResult<HttpResponse> {
+    let (gid, pid) = parse_ids(path.into_inner())?;
+    shared::pull(&state, gid).await;
+
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    gs.authorize(gid, pid, &req)?;
+    // Pollers that already have this generation's board needn't download it again.
+    let etag = header::EntityTag::new_strong(format!("{gid}-{pid}-{}", gs.generation));
+    let unchanged = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v == etag.to_string());
+    if unchanged {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish());
+    }
+    let svg = gs.render(pid);
+    Ok(HttpResponse::Ok()
+        .insert_header(header::ETag(etag))
+        .append_header((header::CONTENT_TYPE, "image/svg+xml"))
+        .body(svg.to_string()))
+}
+
+/// The whole match as an `esgea::Replay` file, once it's over: the map, rules, seed, seats and
+/// every action, enough for a client to play it back.
+#[get("/replay/{gid}")]
+async fn replay(state: Data<Mutex<State>>, path: web::Path<String>) -> ServerResult<HttpResponse> {
+    let gid = parse_gid(&path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let st = state.lock();
+    let replay = match st.games.get(&gid).map(|gs| gs.lock()) {
+        Some(gs) if matches!(gs.status, Status::Finished | Status::Abandoned) => gs.replay(),
+        Some(_) => return Err(ServerError::NotOver),
+        None => st
+            .replays
+            .iter()
+            .find(|(id, _)| *id == gid)
+            .map(|(_, replay)| replay.clone())
+            .ok_or(ServerError::NoSuchGame)?,
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"esgea-{gid}.json\""),
+        ))
+        .json(&*replay))
+}
+
+#[derive(Deserialize)]
+struct ActionQuery {
+    /// Client-chosen id for this action, the same on every resend of it.
+    key: Option<String>,
+}
+
+/// Play `action` as `pid`, answering with its action id. A client that may resend on a flaky
+/// connection can pass `?key=<its own id for the action>`: a resend with a key the seat used
+/// recently gets the first answer back instead of acting again.
+#[post("/do_action/{gid}/{pid}")]
+async fn do_action(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<ActionQuery>,
+    body: Bytes,
+    limits: Data<limit::Limits>,
+) -> ServerResult<HttpResponse> {
+    let (gid, pid) = parse_ids(path.into_inner())?;
+    let action = serde_json::from_slice::<esgea::Action>(body.as_ref())
+        .map_err(|e| ServerError::BadAction(e.to_string()))?;
+
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    gs.authorize(gid, pid, &req)?;
+    limits.actions.check_seat(gid, pid)?;
+    let id = gs.play(pid, action, query.into_inner().key)?;
+    Ok(HttpResponse::Ok().json(id))
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let config = config::Config::load().map_err(std::io::Error::other)?;
+    let secret_key = config.session_key().map_err(std::io::Error::other)?;
+    config.start_logging().map_err(std::io::Error::other)?;
+    let tls = match &config.tls {
+        Some(tls) => Some(tls.server_config().map_err(std::io::Error::other)?),
+        None => None,
+    };
+
+    let ratings = ratings::Ratings::load(config.ratings.clone()).map_err(std::io::Error::other)?;
+    let data = Data::new(Mutex::new(State {
+        games: BTreeMap::new(),
+        queue: queue::Queue::default(),
+        ruleset: config.ruleset.clone(),
+        tournaments: BTreeMap::new(),
+        ratings,
+        replays: VecDeque::new(),
+    }));
+
+    let limits = Data::new(limit::Limits::new(config.proxies.clone()));
+    let notify = Data::new(config.notify.clone());
+
+    if let config::Storage::Redis { url } = &config.storage {
+        shared::connect(data.clone(), url)
+            .await
+            .map_err(|e| std::io::Error::other(format!("connecting to redis: {e}")))?;
+    }
+
+    #[cfg(feature = "grpc")]
+    if let Some(listen) = &config.grpc_listen {
+        let addr = listen
+            .parse()
+            .map_err(|e| std::io::Error::other(format!("grpc_listen {listen:?}: {e}")))?;
+        let serving = grpc::serve(addr, data.clone(), limits.clone(), config.proxies.clone());
+        actix_web::rt::spawn(async move {
+            if let Err(e) = serving.await {
+                tracing::error!(error = %e, "gRPC server stopped");
+            }
+        });
+    }
+
+    stats::start();
+    let reaper = data.clone();
+    actix_web::rt::spawn(async move {
+        let mut tick = actix_web::rt::time::interval(REAP_INTERVAL);
+        loop {
+            tick.tick().await;
+            let mut st = reaper.lock();
+            st.rate_finished();
+            st.reap();
+            stats::reaped();
+        }
+    });
+
+    // Cross-origin pages only get the session cookie if it's allowed across sites.
+    let same_site = if config.cors.credentials {
+        SameSite::None
+    } else {
+        SameSite::Lax
+    };
+    let cors = config.cors.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .app_data(limits.clone())
+            .app_data(notify.clone())
+            .app_data(web::JsonConfig::default().error_handler(|e, _| {
+                ServerError::BadBody(e.to_string()).into()
+            }))
+            .wrap(
+                SessionMiddleware::builder(CookieSessionStore::default(), secret_key.clone())
+                    .cookie_same_site(same_site)
+                    .build(),
+            )
+            .wrap(cors.middleware())
+            .wrap_fn(|req, srv| {
+                let span =
+                    tracing::info_span!("request", method = %req.method(), path = %req.path());
+                let started = Instant::now();
+                let response = srv.call(req);
+                async move {
+                    let response = response.await;
+                    if let Ok(response) = &response {
+                        tracing::info!(
+                            status = response.status().as_u16(),
+                            elapsed = ?started.elapsed(),
+                            "handled"
+                        );
+                    }
+                    response
+                }
+                .instrument(span)
+            })
+            .service(assets::index)
+            .service(assets::static_file)
+            .service(whoami)
+            .service(stats::healthz)
+            .service(stats::stats)
+            .service(do_action)
+            .service(list_games)
+            .service(join_game)
+            .service(bots::add_bot)
+            .service(host::ready)
+            .service(host::begin)
+            .service(host::kick)
+            .service(host::leave)
+            .service(pause::pause)
+            .service(pause::resume)
+            .service(event_stream)
+            .service(events_since)
+            .service(sse_stream)
+            .service(render)
+            .service(replay)
+            .service(player_state)
+            .service(chat::send_chat)
+            .service(chat::recent_chat)
+            .service(notify::set_hook)
+            .service(notify::clear_hook)
+            .service(start_game)
+            .service(queue::enqueue)
+            .service(queue::ticket_status)
+            .service(queue::dequeue)
+            .service(queue::ticket_events)
+            .service(tournament::create_tournament)
+            .service(tournament::register)
+            .service(tournament::start_tournament)
+            .service(tournament::decide)
+            .service(tournament::tournament_status)
+            .service(tournament::entrant_games)
+            .service(ratings::create_account)
+            .service(ratings::leaderboard)
+            .service(ratings::player_rating)
+            .service(end_game)
+            .service(delete_game)
+    });
+    // Session cookies and seat tokens travel in the clear without TLS.
+    let server = match tls {
+        Some(tls) => server.bind_rustls(&config.listen, tls)?,
+        None => server.bind(&config.listen)?,
+    };
+    server.run().await
+}
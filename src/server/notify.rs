@@ -0,0 +1,215 @@
+//! Opt-in turn notifications, for games played a move a day.
+//!
+//! A seat registers a hook with `POST /notify/{gid}`. Whenever the engine starts that seat's
+//! turn and it has no event stream open on this server, the hook is pinged: a JSON POST to a
+//! webhook, or a message to a Matrix room. The server makes those requests itself, so hooks
+//! may only point at the hosts the operator lists in `notify.hosts`.
+//!
+//! Without the `notify` feature hooks can't be registered.
+
+use actix_web::http::Uri;
+use actix_web::web::{self, Data, Json};
+use actix_web::{delete, post, HttpRequest, HttpResponse};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Notify;
+use crate::error::{ServerError, ServerResult};
+use crate::{parse_gid, shared, GameState, State};
+
+/// Where to ping a seat when its turn comes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Hook {
+    /// POST `{gid, pid, game, turn}` as JSON to `url`.
+    Webhook { url: String },
+    /// Post a message in `room` (an id like `!abc:example.org`) as whoever `token`, a Matrix
+    /// access token, belongs to.
+    Matrix {
+        homeserver: String,
+        room: String,
+        token: String,
+    },
+}
+
+/// What a hook is told.
+#[derive(Debug, Clone, Serialize)]
+struct Turn {
+    gid: String,
+    pid: esgea::PlayerId,
+    game: String,
+    turn: u32,
+}
+
+impl Hook {
+    /// The URL the server will call.
+    fn url(&self) -> &str {
+        match self {
+            Hook::Webhook { url } => url,
+            Hook::Matrix { homeserver, .. } => homeserver,
+        }
+    }
+
+    /// Check that the hook is an http(s) URL on a host `notify` allows.
+    fn check(&self, notify: &Notify) -> ServerResult<()> {
+        if !cfg!(feature = "notify") {
+            return Err(ServerError::BadHook(
+                "this server was built without the `notify` feature".into(),
+            ));
+        }
+        let uri: Uri = self
+            .url()
+            .parse()
+            .map_err(|e| ServerError::BadHook(format!("{:?}: {e}", self.url())))?;
+        if !matches!(uri.scheme_str(), Some("http" | "https")) {
+            return Err(ServerError::BadHook(
+                "hooks need an http or https URL".into(),
+            ));
+        }
+        let host = uri.host().unwrap_or_default();
+        if !notify
+            .hosts
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == host)
+        {
+            return Err(ServerError::BadHook(format!(
+                "this server doesn't send notifications to {host:?}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl GameState {
+    /// Ping `pid`'s hook if `upds`, about to be pushed to it, start its turn and it isn't
+    /// watching the game here.
+    pub(crate) fn notify_turn(&self, pid: esgea::PlayerId, upds: &[esgea::Envelope]) {
+        let Some(Some(hook)) = self.hooks.get(pid) else {
+            return;
+        };
+        if self.pid_channels[pid].is_some() {
+            return;
+        }
+        let started = upds
+            .iter()
+            .rev()
+            .find(|e| matches!(e.obs, esgea::Observation::TurnStart { who, .. } if who == pid));
+        if let Some(e) = started {
+            let turn = Turn {
+                gid: self.gid.to_string(),
+                pid,
+                game: self.name.clone(),
+                turn: e.turn,
+            };
+            send(hook.clone(), turn);
+        }
+    }
+}
+
+#[cfg(feature = "notify")]
+fn send(hook: Hook, turn: Turn) {
+    use std::time::Duration;
+
+    actix_web::rt::spawn(async move {
+        let client = awc::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .finish();
+        let sent = match &hook {
+            Hook::Webhook { url } => client.post(url).send_json(&turn).await,
+            Hook::Matrix {
+                homeserver,
+                room,
+                token,
+            } => {
+                // The transaction id makes a retried ping for the same turn a no-op.
+                let url = format!(
+                    "{}/_matrix/client/v3/rooms/{}/send/m.room.message/esgea-{}-{}-{}",
+                    homeserver.trim_end_matches('/'),
+                    escape(room),
+                    turn.gid,
+                    turn.pid,
+                    turn.turn
+                );
+                let body = serde_json::json!({
+                    "msgtype": "m.text",
+                    "body": format!("It's your turn in {} (turn {}).", turn.game, turn.turn),
+                });
+                client.put(url).bearer_auth(token).send_json(&body).await
+            }
+        };
+        match sent {
+            Ok(res) if res.status().is_success() => {
+                tracing::debug!(gid = %turn.gid, pid = turn.pid, "turn notification sent")
+            }
+            Ok(res) => tracing::warn!(
+                gid = %turn.gid,
+                pid = turn.pid,
+                status = res.status().as_u16(),
+                "turn notification refused"
+            ),
+            Err(e) => tracing::warn!(
+                gid = %turn.gid,
+                pid = turn.pid,
+                error = %e,
+                "couldn't send turn notification"
+            ),
+        }
+    });
+}
+
+#[cfg(not(feature = "notify"))]
+fn send(_hook: Hook, _turn: Turn) {}
+
+/// Percent-encode a URL path segment.
+#[cfg(feature = "notify")]
+fn escape(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Have the seat the request identifies pinged when its turn comes, replacing any hook it
+/// had. The body is `{"kind": "webhook", "url"}` or
+/// `{"kind": "matrix", "homeserver", "room", "token"}`.
+#[post("/notify/{gid}")]
+pub async fn set_hook(
+    state: Data<Mutex<State>>,
+    notify: Data<Notify>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: Json<Hook>,
+) -> ServerResult<HttpResponse> {
+    let gid = parse_gid(&path.into_inner())?;
+    let hook = body.into_inner();
+    hook.check(&notify)?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    let pid = gs.identify(gid, &req)?;
+    gs.hooks[pid] = Some(hook);
+    shared::save(&gs);
+    Ok(HttpResponse::Ok().body(()))
+}
+
+/// Stop pinging the seat the request identifies.
+#[delete("/notify/{gid}")]
+pub async fn clear_hook(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> ServerResult<HttpResponse> {
+    let gid = parse_gid(&path.into_inner())?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    let pid = gs.identify(gid, &req)?;
+    gs.hooks[pid] = None;
+    shared::save(&gs);
+    Ok(HttpResponse::Ok().body(()))
+}
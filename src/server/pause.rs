@@ -0,0 +1,126 @@
+use std::time::Instant;
+
+use actix_web::web::{self, Data};
+use actix_web::{post, HttpRequest, HttpResponse};
+use esgea::protocol::{Push, Vote};
+use parking_lot::Mutex;
+
+use crate::error::{ServerError, ServerResult};
+use crate::{parse_gid, shared, GameState, State, Status};
+
+impl GameState {
+    /// Record `pid`'s vote to pause (or resume) and switch once the host or every human seat
+    /// has asked. Returns whether the game switched.
+    fn vote_pause(&mut self, pid: esgea::PlayerId, pausing: bool) -> ServerResult<bool> {
+        match (pausing, self.status) {
+            (true, Status::Running) | (false, Status::Paused) => {}
+            (true, Status::Paused) => return Ok(false),
+            (true, _) => return Err(ServerError::NotRunning),
+            (false, _) => return Err(ServerError::NotPaused),
+        }
+        self.pause_votes.insert(pid);
+        let unanimous = self
+            .humans()
+            .iter()
+            .all(|seat| self.pause_votes.contains(seat));
+        let to = if pausing { Vote::Pause } else { Vote::Resume };
+        if self.host != Some(pid) && !unanimous {
+            self.tell_all(&Push::Asked { by: pid, to });
+            return Ok(false);
+        }
+        self.pause_votes.clear();
+        let now = Instant::now();
+        if pausing {
+            self.status = Status::Paused;
+            self.paused_left = self
+                .deadline
+                .take()
+                .map(|d| d.saturating_duration_since(now));
+            self.tell_all(&Push::Paused { by: pid });
+        } else {
+            self.status = Status::Running;
+            self.deadline = self.paused_left.take().map(|left| now + left);
+            self.tell_all(&Push::Resumed { by: pid });
+        }
+        tracing::info!(pid, ?to, "game paused or resumed");
+        self.touched = now;
+        self.generation += 1;
+        Ok(true)
+    }
+
+    /// Record `pid`'s vote to end the game, and end it once the host or every human seat
+    /// has asked.
+    pub(crate) fn vote_end(&mut self, pid: esgea::PlayerId) {
+        if matches!(self.status, Status::Finished | Status::Abandoned) {
+            return;
+        }
+        self.end_votes.insert(pid);
+        let unanimous = self
+            .humans()
+            .iter()
+            .all(|seat| self.end_votes.contains(seat));
+        if self.host != Some(pid) && !unanimous {
+            self.tell_all(&Push::Asked {
+                by: pid,
+                to: Vote::End,
+            });
+            self.generation += 1;
+            return;
+        }
+        self.end_votes.clear();
+        self.status = Status::Finished;
+        tracing::info!(pid, "game ended early");
+        self.touched = Instant::now();
+        self.generation += 1;
+    }
+
+    pub(crate) fn tell_all(&mut self, push: &Push) {
+        let json = serde_json::to_string(push).unwrap_or_default();
+        for pid in 0..self.tokens.len() {
+            self.deliver(pid, &json);
+        }
+    }
+}
+
+async fn vote(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    gid: &str,
+    pausing: bool,
+) -> ServerResult<HttpResponse> {
+    let gid = parse_gid(gid)?;
+    shared::pull(&state, gid).await;
+    let slot = state.lock().game(gid)?;
+    let mut gs = slot.lock();
+    let pid = gs.identify(gid, &req)?;
+    let _game = gs.span().entered();
+    gs.vote_pause(pid, pausing)?;
+    shared::save(&gs);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": gs.status,
+        "votes": gs.pause_votes,
+    })))
+}
+
+/// Freeze the game: turn clocks stop and actions are refused until it resumes. The host
+/// pauses it at once; otherwise it waits until every human seat has asked. Everyone is sent
+/// `{"asked": {by, to: "pause"}}` for each request and `{"paused": {by}}` when it happens.
+#[post("/pause/{gid}")]
+pub async fn pause(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> ServerResult<HttpResponse> {
+    vote(state, req, &path, true).await
+}
+
+/// Unfreeze a paused game, with the same consent as pausing it; the turn clock picks up
+/// where it stopped.
+#[post("/resume/{gid}")]
+pub async fn resume(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> ServerResult<HttpResponse> {
+    vote(state, req, &path, false).await
+}
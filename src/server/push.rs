@@ -0,0 +1,211 @@
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_web::web::{Bytes, Data};
+use actix_web::HttpResponse;
+use actix_web_actors::ws;
+use esgea::protocol;
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+
+use crate::{GameState, State};
+
+/// Messages an SSE client may fall behind by before it's dropped.
+const SSE_BACKLOG: usize = 64;
+/// How often websocket clients are pinged.
+const HEARTBEAT: Duration = Duration::from_secs(5);
+/// A websocket client silent this long is taken to be gone.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Somewhere a seat's observations and notices are pushed: a websocket, a Server-Sent
+/// Events response, or a stream served some other way, such as over gRPC. Every message is one
+/// JSON document in any case.
+pub enum Subscriber {
+    Ws(Addr<ReceiverStream>),
+    Sse(mpsc::Sender<Bytes>),
+    #[cfg(feature = "grpc")]
+    Channel(mpsc::Sender<String>),
+}
+
+impl Subscriber {
+    /// Send `json`, returning false if the subscriber has gone away or can't keep up. A
+    /// websocket's mailbox isn't bounded, so a burst such as a replay can't look like a
+    /// disconnect.
+    pub fn push(&self, json: &str) -> bool {
+        match self {
+            Subscriber::Ws(addr) => {
+                addr.do_send(Push(json.into()));
+                addr.connected()
+            }
+            Subscriber::Sse(tx) => tx
+                .try_send(Bytes::from(format!("data: {json}\n\n")))
+                .is_ok(),
+            #[cfg(feature = "grpc")]
+            Subscriber::Channel(tx) => tx.try_send(json.into()).is_ok(),
+        }
+    }
+
+    /// Whether the client is still there to receive pushes.
+    pub fn is_open(&self) -> bool {
+        match self {
+            Subscriber::Ws(addr) => addr.connected(),
+            Subscriber::Sse(tx) => !tx.is_closed(),
+            #[cfg(feature = "grpc")]
+            Subscriber::Channel(tx) => !tx.is_closed(),
+        }
+    }
+
+    /// Let go of the subscriber, closing a websocket so its client notices and can reconnect.
+    /// The other kinds end when their sender is dropped.
+    pub fn close(self) {
+        if let Subscriber::Ws(addr) = self {
+            addr.do_send(Close);
+        }
+    }
+
+    /// Send a server message that isn't an observation, e.g. a matchmaking result.
+    pub fn notice(&self, push: &protocol::Push) -> bool {
+        self.push(&serde_json::to_string(push).unwrap_or_default())
+    }
+}
+
+/// A fresh SSE subscriber, and the streaming response that feeds it to the client.
+pub fn sse() -> (Subscriber, HttpResponse) {
+    let (tx, rx) = mpsc::channel(SSE_BACKLOG);
+    let body = futures_util::stream::unfold(rx, |mut rx| async move {
+        let chunk = rx.recv().await?;
+        Some((Ok::<_, actix_web::Error>(chunk), rx))
+    });
+    let response = HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(body);
+    (Subscriber::Sse(tx), response)
+}
+
+impl GameState {
+    /// Tell everyone but `pid` whether `pid` has an event stream open.
+    pub(crate) fn announce_presence(&mut self, pid: esgea::PlayerId, connected: bool) {
+        let json =
+            serde_json::to_string(&protocol::Push::Presence { pid, connected }).unwrap_or_default();
+        for other in (0..self.pid_channels.len()).filter(|&other| other != pid) {
+            if self.pid_channels[other].is_some() {
+                self.deliver(other, &json);
+            }
+        }
+    }
+
+    /// Forget `pid`'s websocket `addr` after it closed, unless it has since been replaced.
+    fn disconnect(&mut self, pid: esgea::PlayerId, addr: &Addr<ReceiverStream>) {
+        let current = self.pid_channels.get(pid).and_then(Option::as_ref);
+        if matches!(current, Some(Subscriber::Ws(current)) if current == addr) {
+            self.pid_channels[pid] = None;
+            self.announce_presence(pid, false);
+        }
+    }
+}
+
+/// A websocket event stream. It pings its client every `HEARTBEAT` and closes once the client
+/// has been silent for `CLIENT_TIMEOUT`, freeing its seat's channel.
+pub struct ReceiverStream {
+    state: Data<Mutex<State>>,
+    /// The game and seat it streams, once subscribed.
+    seat: Option<(u128, esgea::PlayerId)>,
+    /// Last time the client was heard from.
+    heard: Instant,
+}
+
+impl ReceiverStream {
+    pub fn new(state: Data<Mutex<State>>) -> Self {
+        ReceiverStream {
+            state,
+            seat: None,
+            heard: Instant::now(),
+        }
+    }
+}
+
+impl core::ops::Drop for ReceiverStream {
+    fn drop(&mut self) {
+        tracing::debug!(seat = ?self.seat, "dropping an event stream");
+    }
+}
+
+impl Actor for ReceiverStream {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(HEARTBEAT, |act, ctx| {
+            if act.heard.elapsed() > CLIENT_TIMEOUT {
+                tracing::info!(seat = ?act.seat, "event stream timed out");
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        let Some((gid, pid)) = self.seat else { return };
+        let Ok(slot) = self.state.lock().game(gid) else {
+            return;
+        };
+        slot.lock().disconnect(pid, &ctx.address());
+    }
+}
+
+/// Handler for `ws::Message`
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ReceiverStream {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        self.heard = Instant::now();
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Err(_) => ctx.stop(),
+            _ => {}
+        }
+    }
+}
+
+/// Tells a stream which seat it carries, so it can let go of it when it closes.
+pub struct Seated(pub u128, pub esgea::PlayerId);
+impl Message for Seated {
+    type Result = ();
+}
+
+impl Handler<Seated> for ReceiverStream {
+    type Result = ();
+    fn handle(&mut self, Seated(gid, pid): Seated, _: &mut Self::Context) {
+        self.seat = Some((gid, pid));
+    }
+}
+
+/// Closes the stream; its seat has stopped sending to it.
+struct Close;
+impl Message for Close {
+    type Result = ();
+}
+
+impl Handler<Close> for ReceiverStream {
+    type Result = ();
+    fn handle(&mut self, _: Close, ctx: &mut Self::Context) {
+        ctx.close(Some(ws::CloseCode::Away.into()));
+        ctx.stop();
+    }
+}
+
+/// One JSON message for the client.
+pub struct Push(Box<str>);
+impl Message for Push {
+    type Result = ();
+}
+
+impl Handler<Push> for ReceiverStream {
+    type Result = ();
+    fn handle(&mut self, msg: Push, ctx: &mut Self::Context) {
+        ctx.text(&*msg.0)
+    }
+}
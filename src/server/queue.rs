@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix_web::web::{self, Bytes, Data};
+use actix_web::{delete, get, post, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+use esgea::protocol::{Matched, Push};
+
+use crate::error::{ServerError, ServerResult};
+use crate::lobby::{GameOptions, RulesChoice};
+use crate::push::{ReceiverStream, Subscriber};
+use crate::{clock, limit, parse_gid, GameState, State};
+
+/// Matches nobody has picked up are forgotten after this long.
+const MATCHED_TTL: Duration = Duration::from_secs(10 * 60);
+/// Tickets without an event stream are forgotten once not polled for this long.
+const WAITING_TTL: Duration = Duration::from_secs(2 * 60);
+/// Turn limit for matched games, so a player who wanders off can't stall the rest.
+const MATCHED_TURN_SECONDS: u64 = 120;
+
+/// A player waiting to be matched.
+pub struct Ticket {
+    id: u128,
+    pool: Pool,
+    /// Where to announce the match, if the player is listening.
+    sub: Option<Subscriber>,
+    /// When the player last polled the ticket.
+    seen: Instant,
+}
+
+impl Ticket {
+    /// Whether the player still seems to be waiting: listening on an open event stream, or
+    /// polling.
+    fn live(&self, now: Instant) -> bool {
+        match &self.sub {
+            Some(sub) => sub.is_open(),
+            None => now.duration_since(self.seen) <= WAITING_TTL,
+        }
+    }
+}
+
+/// What a ticket is matched on: only tickets asking for the same game play together.
+#[derive(Clone, PartialEq, Eq)]
+struct Pool {
+    size: usize,
+    map: String,
+    /// A ruleset preset, or the server's rules if unset.
+    rules: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Queue {
+    /// Oldest first.
+    waiting: Vec<Ticket>,
+    /// Matches not yet picked up over an event stream, by ticket, with when they were made.
+    matched: BTreeMap<u128, (Matched, Instant)>,
+}
+
+impl Queue {
+    /// Forget tickets whose players have gone, and matches nobody picked up within
+    /// `MATCHED_TTL`.
+    pub(crate) fn expire(&mut self) {
+        let now = Instant::now();
+        self.waiting.retain(|t| t.live(now));
+        self.matched
+            .retain(|_, (_, at)| now.duration_since(*at) <= MATCHED_TTL);
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct QueueRequest {
+    /// Players in the game, including this one.
+    size: usize,
+    map: String,
+    /// A ruleset preset's name (see `esgea::Ruleset::named`); the server's rules if unset.
+    rules: Option<String>,
+}
+
+impl Default for QueueRequest {
+    fn default() -> Self {
+        QueueRequest {
+            size: 2,
+            map: "classic".into(),
+            rules: None,
+        }
+    }
+}
+
+impl State {
+    /// Start a game for every group of tickets that fills one, oldest tickets first. Returns
+    /// the games started, for the caller to spawn their turn clocks.
+    fn matchmake(&mut self) -> Vec<u128> {
+        self.queue.expire();
+        let mut started = vec![];
+        loop {
+            let Some(pool) = self.queue.waiting.iter().find_map(|t| {
+                let peers = self
+                    .queue
+                    .waiting
+                    .iter()
+                    .filter(|o| o.pool == t.pool)
+                    .count();
+                (peers >= t.pool.size).then(|| t.pool.clone())
+            }) else {
+                return started;
+            };
+            let (group, rest) = std::mem::take(&mut self.queue.waiting).into_iter().fold(
+                (vec![], vec![]),
+                |(mut group, mut rest), t| {
+                    if group.len() < pool.size && t.pool == pool {
+                        group.push(t);
+                    } else {
+                        rest.push(t);
+                    }
+                    (group, rest)
+                },
+            );
+            self.queue.waiting = rest;
+            let options = GameOptions {
+                name: "matchmade".into(),
+                max_players: Some(pool.size),
+                map: pool.map,
+                public: false,
+                turn_seconds: Some(MATCHED_TURN_SECONDS),
+                rules: pool.rules.map(RulesChoice::Preset),
+            };
+            let gid: u128 = rand::random();
+            let mut gs = match GameState::new(gid, options, self.ruleset.clone()) {
+                Ok(gs) => {
+                    crate::stats::game_created();
+                    gs
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "couldn't create a matched game");
+                    continue;
+                }
+            };
+            for ticket in group {
+                let (pid, token) = gs.seat(None);
+                // Queueing is asking to play now.
+                gs.ready[pid] = true;
+                let matched = Matched {
+                    gid: gid.to_string(),
+                    pid,
+                    token,
+                };
+                match ticket.sub {
+                    Some(sub) => {
+                        sub.notice(&Push::Matched(matched));
+                        gs.subscribe(pid, sub, Some(0));
+                    }
+                    None => {
+                        self.queue
+                            .matched
+                            .insert(ticket.id, (matched, Instant::now()));
+                    }
+                }
+            }
+            if let Err(e) = gs.start() {
+                tracing::warn!(gid = %gid, error = %e, "couldn't start matched game");
+            }
+            self.games.insert(gid, Arc::new(Mutex::new(gs)));
+            started.push(gid);
+        }
+    }
+}
+
+/// Wait for a game of `size` players on `map`, played by the `rules` preset if given. Returns
+/// `{ticket}`, which stays queued while its event stream is open or it's polled at least every
+/// two minutes. Matched games have a turn clock.
+#[post("/queue")]
+pub async fn enqueue(
+    state: Data<Mutex<State>>,
+    limits: Data<limit::Limits>,
+    req: HttpRequest,
+    body: Bytes,
+) -> ServerResult<HttpResponse> {
+    limits.lobby.check(&req)?;
+    let request: QueueRequest = if body.is_empty() {
+        QueueRequest::default()
+    } else {
+        serde_json::from_slice(&body).map_err(|e| ServerError::BadOptions(e.to_string()))?
+    };
+    let map = esgea::Map::named(&request.map).ok_or(ServerError::NoSuchMap(request.map))?;
+    if request.size < 2 || request.size > map.max_players() {
+        return Err(ServerError::BadOptions(format!(
+            "{} holds 2 to {} players",
+            map.name,
+            map.max_players()
+        )));
+    }
+    if let Some(rules) = &request.rules {
+        if esgea::Ruleset::named(rules).is_none() {
+            return Err(ServerError::NoSuchRuleset(rules.clone()));
+        }
+    }
+    let id: u128 = rand::random();
+    let mut st = state.lock();
+    st.queue.waiting.push(Ticket {
+        id,
+        pool: Pool {
+            size: request.size,
+            map: map.name,
+            rules: request.rules,
+        },
+        sub: None,
+        seen: Instant::now(),
+    });
+    let started = st.matchmake();
+    drop(st);
+    for gid in started {
+        clock::spawn(state.clone(), gid);
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "ticket": id.to_string() })))
+}
+
+/// Poll a ticket: `{"waiting": n}` players in its pool so far, or `{"matched": seat}`.
+#[get("/queue/{ticket}")]
+pub async fn ticket_status(
+    state: Data<Mutex<State>>,
+    path: web::Path<String>,
+) -> ServerResult<HttpResponse> {
+    let id = parse_gid(&path.into_inner())?;
+    let mut st = state.lock();
+    if let Some((matched, _)) = st.queue.matched.remove(&id) {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({ "matched": matched })));
+    }
+    let ticket = st
+        .queue
+        .waiting
+        .iter_mut()
+        .find(|t| t.id == id)
+        .ok_or(ServerError::NoSuchTicket)?;
+    ticket.seen = Instant::now();
+    let pool = ticket.pool.clone();
+    let waiting = st.queue.waiting.iter().filter(|t| t.pool == pool).count();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "waiting": waiting })))
+}
+
+/// Stop waiting.
+#[delete("/queue/{ticket}")]
+pub async fn dequeue(
+    state: Data<Mutex<State>>,
+    path: web::Path<String>,
+) -> ServerResult<HttpResponse> {
+    let id = parse_gid(&path.into_inner())?;
+    let mut st = state.lock();
+    let before = st.queue.waiting.len();
+    st.queue.waiting.retain(|t| t.id != id);
+    if st.queue.waiting.len() == before && st.queue.matched.remove(&id).is_none() {
+        return Err(ServerError::NoSuchTicket);
+    }
+    Ok(HttpResponse::Ok().body(()))
+}
+
+/// An event stream opened before there is a game: receives `{"matched": seat}` and then carries
+/// on as that seat's game event stream.
+#[get("/queue/{ticket}/events")]
+pub async fn ticket_events(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    stream: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    let id = parse_gid(&path.into_inner())?;
+    let mut res = ws::handshake(&req)?;
+    let mut st = state.lock();
+    let matched = st.queue.matched.remove(&id).map(|(matched, _)| matched);
+    if matched.is_none() && !st.queue.waiting.iter().any(|t| t.id == id) {
+        return Err(ServerError::NoSuchTicket.into());
+    }
+    let (addr, stream) =
+        ws::WebsocketContext::create_with_addr(ReceiverStream::new(state.clone()), stream);
+    let sub = Subscriber::Ws(addr);
+    match matched {
+        Some(matched) => {
+            sub.notice(&Push::Matched(matched.clone()));
+            let gid = matched.gid.parse::<u128>().ok();
+            if let Some(gs) = gid.and_then(|gid| st.games.get(&gid)) {
+                gs.lock().subscribe(matched.pid, sub, Some(0));
+            }
+        }
+        None => {
+            if let Some(ticket) = st.queue.waiting.iter_mut().find(|t| t.id == id) {
+                ticket.sub = Some(sub);
+            }
+        }
+    }
+    Ok(res.streaming(stream))
+}
@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use actix_web::web::{self, Data};
+use actix_web::{get, post, HttpRequest, HttpResponse, Responder};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ServerError, ServerResult};
+use crate::{limit, new_token, State, Status};
+
+/// Rating every account starts at.
+const INITIAL: f64 = 1500.0;
+/// Most a single game can move a rating.
+const K: f64 = 32.0;
+const MAX_NAME: usize = 32;
+
+/// A name players can be rated under, claimed with a secret token.
+#[derive(Serialize, Deserialize)]
+pub struct Account {
+    token: String,
+    rating: f64,
+    games: u32,
+    history: Vec<RatingChange>,
+}
+
+/// How one rated game moved an account's rating.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RatingChange {
+    gid: String,
+    won: bool,
+    before: f64,
+    after: f64,
+}
+
+/// Elo ratings for every account, kept in `path` if the server was configured with one.
+#[derive(Default)]
+pub struct Ratings {
+    accounts: BTreeMap<String, Account>,
+    path: Option<PathBuf>,
+}
+
+impl Ratings {
+    /// Ratings stored at `path`, or empty ones if there's nothing there yet.
+    pub fn load(path: Option<PathBuf>) -> Result<Ratings, String> {
+        let accounts = match &path {
+            Some(path) if path.exists() => {
+                let shown = path.display();
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| format!("reading ratings from {shown}: {e}"))?;
+                serde_json::from_str(&text)
+                    .map_err(|e| format!("parsing ratings in {shown}: {e}"))?
+            }
+            _ => BTreeMap::new(),
+        };
+        Ok(Ratings { accounts, path })
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else { return };
+        let json = serde_json::to_string(&self.accounts).unwrap_or_default();
+        if let Err(e) = std::fs::write(path, json) {
+            tracing::error!(path = %path.display(), error = %e, "couldn't save ratings");
+        }
+    }
+
+    /// The name of the account `token` belongs to.
+    pub fn account_for(&self, token: &str) -> Option<String> {
+        self.accounts
+            .iter()
+            .find(|(_, account)| account.token == token)
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Rate a finished game: the winner beats each other rated seat, and losers don't rate
+    /// against one another.
+    fn rate(&mut self, gid: u128, winner: &str, losers: &[String]) {
+        for loser in losers {
+            let (Some(w), Some(l)) = (self.accounts.get(winner), self.accounts.get(loser)) else {
+                continue;
+            };
+            let expected = 1.0 / (1.0 + 10f64.powf((l.rating - w.rating) / 400.0));
+            let delta = K * (1.0 - expected);
+            for (name, delta, won) in [(winner, delta, true), (loser.as_str(), -delta, false)] {
+                if let Some(account) = self.accounts.get_mut(name) {
+                    let before = account.rating;
+                    account.rating += delta;
+                    account.games += 1;
+                    account.history.push(RatingChange {
+                        gid: gid.to_string(),
+                        won,
+                        before,
+                        after: account.rating,
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl State {
+    /// Rate every finished game that hasn't been yet. Games need a winner and at least two
+    /// seats joined with accounts to count.
+    pub(crate) fn rate_finished(&mut self) {
+        let mut rated_any = false;
+        for (&gid, gs) in &self.games {
+            let mut gs = gs.lock();
+            if gs.status != Status::Finished || gs.rated {
+                continue;
+            }
+            gs.rated = true;
+            let Some(winner) = gs.game.lock().winner() else {
+                continue;
+            };
+            let Some(Some(winner)) = gs.accounts.get(winner).cloned() else {
+                continue;
+            };
+            let losers: Vec<String> = gs
+                .accounts
+                .iter()
+                .flatten()
+                .filter(|name| **name != winner)
+                .cloned()
+                .collect();
+            self.ratings.rate(gid, &winner, &losers);
+            rated_any = true;
+        }
+        if rated_any {
+            self.ratings.save();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewAccount {
+    name: String,
+}
+
+/// Claim a name to be rated under, as JSON `{name}`. Returns `{name, token}`; joining games
+/// with `Authorization: Bearer <token>` rates those games.
+#[post("/account")]
+pub async fn create_account(
+    state: Data<Mutex<State>>,
+    limits: Data<limit::Limits>,
+    req: HttpRequest,
+    body: web::Json<NewAccount>,
+) -> ServerResult<HttpResponse> {
+    limits.lobby.check(&req)?;
+    let name = body.into_inner().name.trim().to_owned();
+    if name.is_empty() || name.chars().count() > MAX_NAME {
+        return Err(ServerError::BadOptions(format!(
+            "names are 1 to {MAX_NAME} characters"
+        )));
+    }
+    let mut st = state.lock();
+    if st.ratings.accounts.contains_key(&name) {
+        return Err(ServerError::NameTaken);
+    }
+    let token = new_token();
+    st.ratings.accounts.insert(
+        name.clone(),
+        Account {
+            token: token.clone(),
+            rating: INITIAL,
+            games: 0,
+            history: vec![],
+        },
+    );
+    st.ratings.save();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "name": name, "token": token })))
+}
+
+/// Every account, best first, as `[{name, rating, games}]`.
+#[get("/ratings")]
+pub async fn leaderboard(state: Data<Mutex<State>>) -> impl Responder {
+    let mut st = state.lock();
+    st.rate_finished();
+    let mut board: Vec<_> = st.ratings.accounts.iter().collect();
+    board.sort_by(|(_, a), (_, b)| b.rating.total_cmp(&a.rating));
+    HttpResponse::Ok().json(
+        board
+            .into_iter()
+            .map(|(name, a)| serde_json::json!({ "name": name, "rating": a.rating, "games": a.games }))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// One account's rating and how every rated game changed it.
+#[get("/ratings/{name}")]
+pub async fn player_rating(
+    state: Data<Mutex<State>>,
+    path: web::Path<String>,
+) -> ServerResult<HttpResponse> {
+    let name = path.into_inner();
+    let mut st = state.lock();
+    st.rate_finished();
+    let account = st
+        .ratings
+        .accounts
+        .get(&name)
+        .ok_or(ServerError::NoSuchAccount)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "name": name,
+        "rating": account.rating,
+        "games": account.games,
+        "history": account.history,
+    })))
+}
@@ -0,0 +1,364 @@
+//! Optional Redis backing, so several replicas can serve one lobby.
+//!
+//! With the `redis` feature and Redis storage configured, every change to a game is written
+//! through to Redis as a snapshot, and a replica pulls the latest snapshot before handling a
+//! request for that game. Pushes for a seat whose event stream is held by another replica are
+//! forwarded over pub/sub, chat included (though chat history stays on the replica that saw it).
+//! Snapshots are last-writer-wins by generation: two replicas acting on the same game at the same
+//! instant can lose one of the actions, so a load balancer should still prefer sticky routing by
+//! game. Matchmaking queues stay local to each replica.
+//!
+//! A replica reaps only its own copy of a game. The shared snapshot expires once no replica has
+//! saved it for as long as the game may sit idle and then be kept, or goes at once when the game
+//! is deleted.
+//!
+//! Without the feature every function here is a no-op.
+
+#[cfg(feature = "redis")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "redis")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "redis")]
+use crate::{GameState, Status};
+
+/// Everything about a game that must survive moving between replicas. Event-stream
+/// subscribers, chat and caches stay with the replica that has them.
+#[cfg(feature = "redis")]
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    gid: u128,
+    game: esgea::Game,
+    name: String,
+    map: esgea::Map,
+    #[serde(default)]
+    preset: Option<String>,
+    max_players: usize,
+    join_code: Option<String>,
+    status: Status,
+    turn_seconds: Option<u64>,
+    tokens: Vec<String>,
+    teams: Vec<Option<u32>>,
+    #[serde(default)]
+    bots: std::collections::BTreeMap<esgea::PlayerId, esgea::BotLevel>,
+    #[serde(default)]
+    sent: Vec<u64>,
+    #[serde(default)]
+    accounts: Vec<Option<String>>,
+    #[serde(default)]
+    recent: Vec<std::collections::VecDeque<(String, esgea::ActionId)>>,
+    #[serde(default)]
+    hooks: Vec<Option<crate::notify::Hook>>,
+    #[serde(default)]
+    rated: bool,
+    #[serde(default)]
+    paused_left: Option<Duration>,
+    #[serde(default)]
+    pause_votes: std::collections::BTreeSet<esgea::PlayerId>,
+    #[serde(default)]
+    end_votes: std::collections::BTreeSet<esgea::PlayerId>,
+    #[serde(default)]
+    host: Option<esgea::PlayerId>,
+    #[serde(default)]
+    ready: Vec<bool>,
+    generation: u64,
+}
+
+#[cfg(feature = "redis")]
+impl Snapshot {
+    fn of(gs: &GameState) -> Snapshot {
+        Snapshot {
+            gid: gs.gid,
+            game: gs.game.lock().clone(),
+            name: gs.name.clone(),
+            map: gs.map.clone(),
+            preset: gs.preset.clone(),
+            max_players: gs.max_players,
+            join_code: gs.join_code.clone(),
+            status: gs.status,
+            turn_seconds: gs.turn_limit.map(|limit| limit.as_secs()),
+            tokens: gs.tokens.clone(),
+            teams: gs.teams.clone(),
+            bots: gs.bots.clone(),
+            sent: gs.sent.clone(),
+            accounts: gs.accounts.clone(),
+            recent: gs.recent.clone(),
+            hooks: gs.hooks.clone(),
+            rated: gs.rated,
+            paused_left: gs.paused_left,
+            pause_votes: gs.pause_votes.clone(),
+            end_votes: gs.end_votes.clone(),
+            host: gs.host,
+            ready: gs.ready.clone(),
+            generation: gs.generation,
+        }
+    }
+
+    /// Bring `gs` up to this snapshot, keeping its local subscribers and chat.
+    fn apply(self, gs: &mut GameState) {
+        *gs.game.lock() = self.game;
+        gs.name = self.name;
+        gs.map = self.map;
+        gs.preset = self.preset;
+        gs.max_players = self.max_players;
+        gs.join_code = self.join_code;
+        gs.status = self.status;
+        gs.turn_limit = self.turn_seconds.map(Duration::from_secs);
+        gs.pid_channels.resize_with(self.tokens.len(), || None);
+        gs.tokens = self.tokens;
+        gs.teams = self.teams;
+        gs.bots = self.bots;
+        gs.sent = self.sent;
+        gs.accounts = self.accounts;
+        gs.recent = self.recent;
+        gs.recent.resize_with(gs.tokens.len(), Default::default);
+        gs.hooks = self.hooks;
+        gs.hooks.resize_with(gs.tokens.len(), Default::default);
+        gs.rated = self.rated;
+        gs.paused_left = self.paused_left;
+        gs.pause_votes = self.pause_votes;
+        gs.end_votes = self.end_votes;
+        gs.host = self.host;
+        gs.ready = self.ready;
+        gs.generation = self.generation;
+        gs.renders.clear();
+        gs.touched = Instant::now();
+        gs.wind_clock();
+    }
+}
+
+#[cfg(feature = "redis")]
+mod backend {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, OnceLock};
+    use std::time::Duration;
+
+    use actix_web::web::Data;
+    use futures_util::StreamExt;
+    use parking_lot::Mutex;
+    use redis::aio::MultiplexedConnection;
+    use redis::AsyncCommands;
+    use serde::{Deserialize, Serialize};
+
+    use super::Snapshot;
+    use crate::{GameState, State, RETAIN_TTL};
+
+    const GAMES: &str = "esgea:games";
+    /// Pub/sub channel for pushes to seats whose stream another replica holds.
+    const PUSH: &str = "esgea:push";
+
+    struct Shared {
+        conn: MultiplexedConnection,
+        /// Tells our own forwarded pushes apart from other replicas'.
+        instance: u128,
+    }
+
+    static SHARED: OnceLock<Shared> = OnceLock::new();
+    /// The pub/sub relay is still running.
+    static RELAYING: AtomicBool = AtomicBool::new(false);
+
+    fn key(gid: u128) -> String {
+        format!("esgea:game:{gid}")
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Forward {
+        origin: u128,
+        gid: u128,
+        pid: esgea::PlayerId,
+        json: String,
+    }
+
+    /// Connect to the Redis at `url` and start relaying other replicas' pushes to
+    /// subscribers held here.
+    pub async fn connect(state: Data<Mutex<State>>, url: &str) -> Result<(), String> {
+        relay(state, url).await.map_err(|e| e.to_string())
+    }
+
+    async fn relay(state: Data<Mutex<State>>, url: &str) -> redis::RedisResult<()> {
+        let client = redis::Client::open(url)?;
+        let conn = client.get_multiplexed_tokio_connection().await?;
+        let mut pubsub = client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(PUSH).await?;
+        let instance = rand::random();
+        let _ = SHARED.set(Shared { conn, instance });
+        RELAYING.store(true, Ordering::Relaxed);
+        actix_web::rt::spawn(async move {
+            let mut messages = pubsub.into_on_message();
+            while let Some(msg) = messages.next().await {
+                let Ok(payload) = msg.get_payload::<String>() else {
+                    continue;
+                };
+                let Ok(fwd) = serde_json::from_str::<Forward>(&payload) else {
+                    continue;
+                };
+                if fwd.origin == instance {
+                    continue;
+                }
+                let Ok(slot) = state.lock().game(fwd.gid) else {
+                    continue;
+                };
+                let gs = slot.lock();
+                if let Some(Some(sub)) = gs.pid_channels.get(fwd.pid) {
+                    sub.push(&fwd.json);
+                }
+            }
+            RELAYING.store(false, Ordering::Relaxed);
+            tracing::error!("lost the redis subscription; no longer relaying pushes");
+        });
+        Ok(())
+    }
+
+    /// Whether Redis answers and other replicas' pushes are still being relayed.
+    pub async fn check() -> Result<(), String> {
+        let Some(shared) = SHARED.get() else {
+            return Ok(());
+        };
+        if !RELAYING.load(Ordering::Relaxed) {
+            return Err("lost the redis subscription".into());
+        }
+        let mut conn = shared.conn.clone();
+        let ping = redis::cmd("PING");
+        let ping = ping.query_async::<_, String>(&mut conn);
+        match actix_web::rt::time::timeout(Duration::from_secs(2), ping).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(format!("redis: {e}")),
+            Err(_) => Err("redis didn't answer within 2s".into()),
+        }
+    }
+
+    /// The shared snapshot of `gid`, or `Ok(None)` if there isn't one, e.g. because it expired.
+    async fn load(gid: u128) -> Result<Option<Snapshot>, String> {
+        let Some(shared) = SHARED.get() else {
+            return Ok(None);
+        };
+        let json: Option<String> = shared
+            .conn
+            .clone()
+            .get(key(gid))
+            .await
+            .map_err(|e| e.to_string())?;
+        json.map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .transpose()
+    }
+
+    /// Bring our copy of `gid` up to date with the shared one, adopting it if we don't have it.
+    pub async fn pull(state: &Mutex<State>, gid: u128) {
+        if let Ok(Some(snapshot)) = load(gid).await {
+            adopt(state, gid, snapshot);
+        }
+    }
+
+    fn adopt(state: &Mutex<State>, gid: u128, snapshot: Snapshot) {
+        let mut st = state.lock();
+        if let Ok(slot) = st.game(gid) {
+            drop(st);
+            let mut gs = slot.lock();
+            if gs.generation < snapshot.generation {
+                snapshot.apply(&mut gs);
+            }
+            return;
+        }
+        let ruleset = st.ruleset.clone();
+        let Ok(mut gs) = GameState::new(gid, Default::default(), ruleset) else {
+            return;
+        };
+        snapshot.apply(&mut gs);
+        st.games.insert(gid, Arc::new(Mutex::new(gs)));
+    }
+
+    /// Pull every shared game, e.g. before listing the lobby, and forget those that expired.
+    pub async fn pull_all(state: &Mutex<State>) {
+        let Some(shared) = SHARED.get() else { return };
+        let mut conn = shared.conn.clone();
+        let gids: Vec<String> = conn.smembers(GAMES).await.unwrap_or_default();
+        let mut expired = vec![];
+        for gid in gids {
+            let Ok(parsed) = gid.parse() else { continue };
+            match load(parsed).await {
+                Ok(Some(snapshot)) => adopt(state, parsed, snapshot),
+                Ok(None) => expired.push(gid),
+                Err(e) => tracing::warn!(gid = %gid, error = %e, "couldn't load game from redis"),
+            }
+        }
+        if !expired.is_empty() {
+            let _: redis::RedisResult<()> = conn.srem(GAMES, expired).await;
+        }
+    }
+
+    /// Write `gs` through to Redis in the background, to expire if it's left unsaved for longer
+    /// than any replica would keep it.
+    pub fn save(gs: &GameState) {
+        let Some(shared) = SHARED.get() else { return };
+        let Ok(json) = serde_json::to_string(&Snapshot::of(gs)) else {
+            return;
+        };
+        let gid = gs.gid;
+        let ttl = (gs.idle_ttl() + RETAIN_TTL).as_secs() as usize;
+        let mut conn = shared.conn.clone();
+        actix_web::rt::spawn(async move {
+            let saved: redis::RedisResult<()> = redis::pipe()
+                .set_ex(key(gid), json, ttl)
+                .sadd(GAMES, gid.to_string())
+                .query_async(&mut conn)
+                .await;
+            if let Err(e) = saved {
+                tracing::warn!(gid = %gid, error = %e, "couldn't save game to redis");
+            }
+        });
+    }
+
+    /// Forget `gid` everywhere, when it's deleted.
+    pub fn remove(gid: u128) {
+        let Some(shared) = SHARED.get() else { return };
+        let mut conn = shared.conn.clone();
+        actix_web::rt::spawn(async move {
+            let _: redis::RedisResult<()> = redis::pipe()
+                .del(key(gid))
+                .srem(GAMES, gid.to_string())
+                .query_async(&mut conn)
+                .await;
+        });
+    }
+
+    /// Hand `json` for seat `pid` to whichever replica holds its event stream.
+    pub fn forward(gid: u128, pid: esgea::PlayerId, json: &str) {
+        let Some(shared) = SHARED.get() else { return };
+        let fwd = Forward {
+            origin: shared.instance,
+            gid,
+            pid,
+            json: json.to_owned(),
+        };
+        let Ok(payload) = serde_json::to_string(&fwd) else {
+            return;
+        };
+        let mut conn = shared.conn.clone();
+        actix_web::rt::spawn(async move {
+            let _: redis::RedisResult<()> = conn.publish(PUSH, payload).await;
+        });
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+mod backend {
+    use actix_web::web::Data;
+    use parking_lot::Mutex;
+
+    use crate::{GameState, State};
+
+    pub async fn connect(_state: Data<Mutex<State>>, _url: &str) -> Result<(), String> {
+        Err("this server was built without the `redis` feature".into())
+    }
+    pub async fn pull(_state: &Mutex<State>, _gid: u128) {}
+    pub async fn pull_all(_state: &Mutex<State>) {}
+    pub fn save(_gs: &GameState) {}
+    pub fn remove(_gid: u128) {}
+    pub fn forward(_gid: u128, _pid: esgea::PlayerId, _json: &str) {}
+    pub async fn check() -> Result<(), String> {
+        Ok(())
+    }
+}
+
+pub use backend::*;
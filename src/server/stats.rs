@@ -0,0 +1,114 @@
+//! Probes for running under an orchestrator: `/healthz` says whether this replica is working,
+//! `/stats` what it has done since it started.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use actix_web::web::{self, Data};
+use actix_web::{get, HttpResponse};
+use parking_lot::Mutex;
+
+use crate::{shared, State, Status, REAP_INTERVAL};
+
+/// Longest the state lock may be waited on before the server counts as wedged.
+const LOCK_PATIENCE: Duration = Duration::from_secs(2);
+
+static STARTED: OnceLock<Instant> = OnceLock::new();
+static GAMES_CREATED: AtomicU64 = AtomicU64::new(0);
+static ACTIONS: AtomicU64 = AtomicU64::new(0);
+/// Milliseconds after `STARTED` the reaper last finished a pass.
+static REAPED: AtomicU64 = AtomicU64::new(0);
+
+/// Start the uptime clock.
+pub fn start() {
+    STARTED.get_or_init(Instant::now);
+}
+
+fn uptime() -> Duration {
+    STARTED.get().map_or(Duration::ZERO, Instant::elapsed)
+}
+
+pub fn game_created() {
+    GAMES_CREATED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count an action a player or bot played.
+pub fn acted() {
+    ACTIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Note that the reaper is still going.
+pub fn reaped() {
+    REAPED.store(uptime().as_millis() as u64, Ordering::Relaxed);
+}
+
+/// `200 {"ok": true, "checks"}` if the replica can serve, or `503` with `"ok": false`. Each
+/// check is `"ok"` or what's wrong:
+/// - `storage`: the shared storage answers, if there is any;
+/// - `reaper`: the reaper has run within a few intervals, so background tasks are being
+///   scheduled;
+/// - `state`: the server-wide lock can be taken.
+///
+/// Fit for both liveness and readiness probes.
+#[get("/healthz")]
+pub async fn healthz(state: Data<Mutex<State>>) -> HttpResponse {
+    // Wait for the lock on the blocking pool, not on the worker serving other requests.
+    let locked = {
+        let state = state.clone();
+        web::block(move || state.try_lock_for(LOCK_PATIENCE).is_some()).await
+    };
+    let since_reap = uptime().saturating_sub(Duration::from_millis(REAPED.load(Ordering::Relaxed)));
+    let checks = [
+        ("storage", shared::check().await),
+        (
+            "reaper",
+            if since_reap > REAP_INTERVAL * 3 {
+                Err(format!("last ran {}s ago", since_reap.as_secs()))
+            } else {
+                Ok(())
+            },
+        ),
+        (
+            "state",
+            match locked {
+                Ok(true) => Ok(()),
+                Ok(false) => Err(format!("held for over {}s", LOCK_PATIENCE.as_secs())),
+                Err(e) => Err(e.to_string()),
+            },
+        ),
+    ];
+    let ok = checks.iter().all(|(_, check)| check.is_ok());
+    let checks: serde_json::Map<_, _> = checks
+        .into_iter()
+        .map(|(name, check)| {
+            (
+                name.to_owned(),
+                check.err().unwrap_or_else(|| "ok".into()).into(),
+            )
+        })
+        .collect();
+    let body = serde_json::json!({ "ok": ok, "checks": checks });
+    if ok {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+/// Uptime, games created and actions played since this replica started, and its games by
+/// status now.
+#[get("/stats")]
+pub async fn stats(state: Data<Mutex<State>>) -> HttpResponse {
+    let games: Vec<_> = state.lock().games.values().cloned().collect();
+    let mut by_status = std::collections::BTreeMap::<Status, usize>::new();
+    for slot in games {
+        *by_status.entry(slot.lock().status).or_default() += 1;
+    }
+    HttpResponse::Ok().json(serde_json::json!({
+        "uptime_secs": uptime().as_secs(),
+        "games_created": GAMES_CREATED.load(Ordering::Relaxed),
+        "actions": ACTIONS.load(Ordering::Relaxed),
+        "games": by_status,
+    }))
+}
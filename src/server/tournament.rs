@@ -0,0 +1,668 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix_web::rt::time;
+use actix_web::web::{self, Bytes, Data};
+use actix_web::{get, post, HttpRequest, HttpResponse};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ServerError, ServerResult};
+use crate::lobby::GameOptions;
+use crate::{
+    clock, limit, new_token, parse_gid, request_token, shared, GameState, State, Status, IDLE_TTL,
+};
+
+/// How often running tournaments check their games for results.
+const POLL: Duration = Duration::from_secs(2);
+const MAX_ENTRANTS: usize = 64;
+/// Longest tournament or entrant name, in characters.
+const MAX_NAME: usize = 32;
+/// Complete tournaments are kept this long for their standings, then dropped.
+const COMPLETE_TTL: Duration = Duration::from_secs(60 * 60);
+/// Times a game that ends undecided is replayed before the organizer has to settle it.
+const REPLAYS: u32 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    SingleElimination,
+    /// Entrants are out after their second loss; the last one standing wins.
+    DoubleElimination,
+    /// Everyone plays everyone once.
+    RoundRobin,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Registering,
+    Running,
+    Complete,
+}
+
+/// Options for `POST /tournament`.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct TournamentRequest {
+    name: String,
+    format: Format,
+    /// A built-in map name; see `esgea::Map::named`.
+    map: String,
+    turn_seconds: Option<u64>,
+}
+
+impl Default for TournamentRequest {
+    fn default() -> Self {
+        TournamentRequest {
+            name: String::new(),
+            format: Format::SingleElimination,
+            map: "classic".into(),
+            turn_seconds: None,
+        }
+    }
+}
+
+struct Entrant {
+    name: String,
+    token: String,
+}
+
+/// One game of a round, between entrants `a` and `b` (indices into `entrants`). A pairing
+/// without `b` is a bye, which `a` wins outright.
+#[derive(Debug, Clone, Serialize)]
+struct Pairing {
+    a: usize,
+    b: Option<usize>,
+    #[serde(serialize_with = "gid_string")]
+    gid: Option<u128>,
+    /// Seat tokens for `a` and `b`, handed out through `/tournament/{tid}/games`.
+    #[serde(skip)]
+    tokens: Option<(String, String)>,
+    /// `None` until decided; stays `None` for a drawn round-robin game.
+    winner: Option<usize>,
+    decided: bool,
+    /// Games started again because the last ended undecided: abandoned, reaped, or (outside a
+    /// round robin) over without a winner.
+    replays: u32,
+    /// Out of replays; waits for the organizer to settle it with `/tournament/{tid}/decide`.
+    disputed: bool,
+}
+
+fn gid_string<S: serde::Serializer>(gid: &Option<u128>, s: S) -> Result<S::Ok, S::Error> {
+    gid.map(|gid| gid.to_string()).serialize(s)
+}
+
+pub struct Tournament {
+    name: String,
+    format: Format,
+    map: String,
+    turn_seconds: Option<u64>,
+    /// Token of whoever created the tournament; needed to start it.
+    organizer: String,
+    entrants: Vec<Entrant>,
+    stage: Stage,
+    rounds: Vec<Vec<Pairing>>,
+    /// When someone last registered, or when it completed.
+    touched: Instant,
+}
+
+/// One line of the standings.
+#[derive(Serialize)]
+struct Standing {
+    entrant: usize,
+    name: String,
+    wins: u32,
+    losses: u32,
+    draws: u32,
+}
+
+impl Tournament {
+    /// Whether it's done with: complete for `COMPLETE_TTL`, or still registering but with no
+    /// one entering for `IDLE_TTL`.
+    fn expired(&self, now: Instant) -> bool {
+        let idle = now.duration_since(self.touched);
+        match self.stage {
+            Stage::Registering => idle > IDLE_TTL,
+            Stage::Running => false,
+            Stage::Complete => idle > COMPLETE_TTL,
+        }
+    }
+
+    fn standings(&self) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = self
+            .entrants
+            .iter()
+            .enumerate()
+            .map(|(entrant, e)| Standing {
+                entrant,
+                name: e.name.clone(),
+                wins: 0,
+                losses: 0,
+                draws: 0,
+            })
+            .collect();
+        for pairing in self.rounds.iter().flatten().filter(|p| p.decided) {
+            let Some(b) = pairing.b else {
+                standings[pairing.a].wins += 1;
+                continue;
+            };
+            match pairing.winner {
+                Some(winner) => {
+                    let loser = if winner == pairing.a { b } else { pairing.a };
+                    standings[winner].wins += 1;
+                    standings[loser].losses += 1;
+                }
+                None => {
+                    standings[pairing.a].draws += 1;
+                    standings[b].draws += 1;
+                }
+            }
+        }
+        match self.format {
+            Format::RoundRobin => standings.sort_by_key(|s| Reverse(2 * s.wins + s.draws)),
+            _ => standings.sort_by_key(|s| (s.losses, Reverse(s.wins))),
+        }
+        standings
+    }
+
+    /// The pairings of the next round, or `None` once the tournament is decided.
+    fn next_round(&self) -> Option<Vec<(usize, Option<usize>)>> {
+        let n = self.entrants.len();
+        let lives = match self.format {
+            Format::RoundRobin => return round_robin(n, self.rounds.len()),
+            Format::SingleElimination => 1,
+            Format::DoubleElimination => 2,
+        };
+        let alive: Vec<Standing> = self
+            .standings()
+            .into_iter()
+            .filter(|s| s.losses < lives)
+            .collect();
+        if alive.len() < 2 {
+            return None;
+        }
+        // Pair entrants with the same record, so a loss never costs more than it should; the
+        // best of an odd group sits the round out. Only once no two entrants share a record
+        // (e.g. a double elimination's grand final) do records meet.
+        let mut pairings = vec![];
+        let mut odd = vec![];
+        for losses in 0..lives {
+            let group: Vec<usize> = alive
+                .iter()
+                .filter(|s| s.losses == losses)
+                .map(|s| s.entrant)
+                .collect();
+            let (bye, rest) = group.split_at(group.len() % 2);
+            odd.extend_from_slice(bye);
+            pairings.extend(rest.chunks(2).map(|pair| (pair[0], Some(pair[1]))));
+        }
+        if pairings.is_empty() {
+            let (bye, rest) = odd.split_at(odd.len() % 2);
+            pairings.extend(rest.chunks(2).map(|pair| (pair[0], Some(pair[1]))));
+            odd = bye.to_vec();
+        }
+        pairings.extend(odd.into_iter().map(|entrant| (entrant, None)));
+        Some(pairings)
+    }
+
+    /// Start round `number`'s game between entrants `a` and `b`, returning it with their seat
+    /// tokens.
+    fn open_game(
+        &self,
+        number: usize,
+        a: usize,
+        b: usize,
+        ruleset: esgea::Ruleset,
+    ) -> ServerResult<(GameState, (String, String))> {
+        let options = GameOptions {
+            name: format!(
+                "{} round {number}: {} vs {}",
+                self.name, self.entrants[a].name, self.entrants[b].name
+            ),
+            max_players: Some(2),
+            map: self.map.clone(),
+            public: false,
+            turn_seconds: self.turn_seconds,
+            rules: None,
+        };
+        let mut gs = GameState::new(rand::random(), options, ruleset)?;
+        crate::stats::game_created();
+        let (_, token_a) = gs.seat(None);
+        let (_, token_b) = gs.seat(None);
+        // Entering is agreeing to play each pairing.
+        gs.ready = vec![true; 2];
+        gs.start()?;
+        Ok((gs, (token_a, token_b)))
+    }
+}
+
+/// Round `round` of a circle-method round robin among `n` entrants.
+fn round_robin(n: usize, round: usize) -> Option<Vec<(usize, Option<usize>)>> {
+    let mut seats: Vec<Option<usize>> = (0..n).map(Some).collect();
+    if n % 2 == 1 {
+        seats.push(None);
+    }
+    let m = seats.len();
+    if round >= m - 1 {
+        return None;
+    }
+    seats[1..].rotate_right(round);
+    let pairings = (0..m / 2)
+        .filter_map(|i| match (seats[i], seats[m - 1 - i]) {
+            (Some(a), b) => Some((a, b)),
+            (None, b) => b.map(|b| (b, None)),
+        })
+        .collect();
+    Some(pairings)
+}
+
+impl State {
+    /// Drop tournaments that are done with.
+    pub(crate) fn reap_tournaments(&mut self, now: Instant) {
+        self.tournaments.retain(|tid, tournament| {
+            let expired = tournament.expired(now);
+            if expired {
+                tracing::info!(tid = %tid, stage = ?tournament.stage, "dropping tournament");
+            }
+            !expired
+        });
+    }
+
+    /// Record results from `tid`'s finished games, and start the next round once the current
+    /// one is decided. A game that ends without a winner is a draw in a round robin; otherwise,
+    /// as when a game is abandoned or reaped before we see it end, the pairing is replayed, and
+    /// once out of replays it's disputed until the organizer settles it.
+    ///
+    /// Returns the games started with a turn clock, for the caller to spawn their clocks.
+    fn advance_tournament(&mut self, tid: u128) -> Vec<u128> {
+        let mut timed = vec![];
+        let Some(tournament) = self.tournaments.get_mut(&tid) else {
+            return timed;
+        };
+        if tournament.stage != Stage::Running {
+            return timed;
+        }
+        let format = tournament.format;
+        let number = tournament.rounds.len();
+        let mut undecided = vec![];
+        if let Some(round) = tournament.rounds.last_mut() {
+            for (i, pairing) in round.iter_mut().enumerate() {
+                if pairing.decided || pairing.disputed {
+                    continue;
+                }
+                let (Some(b), Some(gid)) = (pairing.b, pairing.gid) else {
+                    continue;
+                };
+                let winner = match self.games.get(&gid).map(|gs| gs.lock()) {
+                    Some(gs) if gs.status == Status::Finished => gs.game.lock().winner(),
+                    Some(gs) if gs.status != Status::Abandoned => continue,
+                    // Abandoned, or reaped before we saw it end.
+                    _ => {
+                        undecided.push((i, b));
+                        continue;
+                    }
+                };
+                pairing.winner = match winner {
+                    Some(0) => Some(pairing.a),
+                    Some(_) => Some(b),
+                    None if format == Format::RoundRobin => None,
+                    None => {
+                        undecided.push((i, b));
+                        continue;
+                    }
+                };
+                pairing.decided = true;
+            }
+        }
+        for (i, b) in undecided {
+            let pairing = &tournament.rounds[number - 1][i];
+            let a = pairing.a;
+            let opened = (pairing.replays < REPLAYS)
+                .then(|| tournament.open_game(number, a, b, self.ruleset.clone()));
+            let pairing = &mut tournament.rounds[number - 1][i];
+            match opened {
+                Some(Ok((gs, tokens))) => {
+                    tracing::info!(tid = %tid, gid = %gs.gid, "replaying an undecided tournament game");
+                    pairing.replays += 1;
+                    pairing.gid = Some(gs.gid);
+                    pairing.tokens = Some(tokens);
+                    if gs.turn_limit.is_some() {
+                        timed.push(gs.gid);
+                    }
+                    self.games.insert(gs.gid, Arc::new(Mutex::new(gs)));
+                }
+                Some(Err(e)) => {
+                    tracing::warn!(tid = %tid, error = %e, "couldn't replay a tournament game");
+                    pairing.disputed = true;
+                }
+                None => {
+                    tracing::info!(tid = %tid, "tournament game out of replays, left to the organizer");
+                    pairing.disputed = true;
+                }
+            }
+        }
+        if let Some(round) = tournament.rounds.last() {
+            if round.iter().any(|p| !p.decided) {
+                return timed;
+            }
+        }
+        let Some(pairings) = tournament.next_round() else {
+            tournament.stage = Stage::Complete;
+            tournament.touched = Instant::now();
+            return timed;
+        };
+        let number = tournament.rounds.len() + 1;
+        let mut round = vec![];
+        for (a, b) in pairings {
+            let mut pairing = Pairing {
+                a,
+                b,
+                gid: None,
+                tokens: None,
+                winner: None,
+                decided: false,
+                replays: 0,
+                disputed: false,
+            };
+            let Some(b) = b else {
+                pairing.winner = Some(a);
+                pairing.decided = true;
+                round.push(pairing);
+                continue;
+            };
+            match tournament.open_game(number, a, b, self.ruleset.clone()) {
+                Ok((gs, tokens)) => {
+                    pairing.gid = Some(gs.gid);
+                    pairing.tokens = Some(tokens);
+                    if gs.turn_limit.is_some() {
+                        timed.push(gs.gid);
+                    }
+                    self.games.insert(gs.gid, Arc::new(Mutex::new(gs)));
+                }
+                Err(e) => {
+                    tracing::warn!(tid = %tid, error = %e, "couldn't create a tournament game");
+                    pairing.disputed = true;
+                }
+            }
+            round.push(pairing);
+        }
+        tournament.rounds.push(round);
+        // A round of nothing but byes is already decided.
+        timed.extend(self.advance_tournament(tid));
+        timed
+    }
+}
+
+/// Advance `tid` as its games finish, until it's complete or gone.
+fn spawn(state: Data<Mutex<State>>, tid: u128) {
+    actix_web::rt::spawn(async move {
+        loop {
+            time::sleep(POLL).await;
+            let gids: Vec<u128> = {
+                let st = state.lock();
+                let Some(tournament) = st.tournaments.get(&tid) else {
+                    return;
+                };
+                if tournament.stage == Stage::Complete {
+                    return;
+                }
+                let round = tournament.rounds.last().into_iter().flatten();
+                round.filter(|p| !p.decided).filter_map(|p| p.gid).collect()
+            };
+            for gid in gids {
+                shared::pull(&state, gid).await;
+            }
+            let timed = state.lock().advance_tournament(tid);
+            for gid in timed {
+                clock::spawn(state.clone(), gid);
+            }
+        }
+    });
+}
+
+/// Create a tournament from optional JSON `{name, format, map, turn_seconds}`, where `format`
+/// is `single_elimination` (the default), `double_elimination` or `round_robin`. Returns
+/// `{tid, token}`; the token is needed to start it.
+#[post("/tournament")]
+pub async fn create_tournament(
+    state: Data<Mutex<State>>,
+    limits: Data<limit::Limits>,
+    req: HttpRequest,
+    body: Bytes,
+) -> ServerResult<HttpResponse> {
+    limits.lobby.check(&req)?;
+    let request: TournamentRequest = if body.is_empty() {
+        TournamentRequest::default()
+    } else {
+        serde_json::from_slice(&body).map_err(|e| ServerError::BadOptions(e.to_string()))?
+    };
+    if request.name.chars().count() > MAX_NAME {
+        return Err(ServerError::BadOptions(format!(
+            "names are at most {MAX_NAME} characters"
+        )));
+    }
+    let map = esgea::Map::named(&request.map).ok_or(ServerError::NoSuchMap(request.map))?;
+    let tid: u128 = rand::random();
+    let organizer = new_token();
+    state.lock().tournaments.insert(
+        tid,
+        Tournament {
+            name: request.name,
+            format: request.format,
+            map: map.name,
+            turn_seconds: request.turn_seconds,
+            organizer: organizer.clone(),
+            entrants: vec![],
+            stage: Stage::Registering,
+            rounds: vec![],
+            touched: Instant::now(),
+        },
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "tid": tid.to_string(), "token": organizer })))
+}
+
+#[derive(Deserialize)]
+pub struct Registration {
+    name: String,
+}
+
+/// Enter a tournament that hasn't started as JSON `{name}`, which no other entrant may share.
+/// Returns `{entrant, token}`; the token fetches the entrant's seats from
+/// `/tournament/{tid}/games`.
+#[post("/tournament/{tid}/register")]
+pub async fn register(
+    state: Data<Mutex<State>>,
+    limits: Data<limit::Limits>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<Registration>,
+) -> ServerResult<HttpResponse> {
+    limits.lobby.check(&req)?;
+    let tid = parse_gid(&path.into_inner())?;
+    let name = body.into_inner().name.trim().to_owned();
+    if name.is_empty() || name.chars().count() > MAX_NAME {
+        return Err(ServerError::BadOptions(format!(
+            "names are 1 to {MAX_NAME} characters"
+        )));
+    }
+    let mut st = state.lock();
+    let tournament = st
+        .tournaments
+        .get_mut(&tid)
+        .ok_or(ServerError::NoSuchTournament)?;
+    if tournament.stage != Stage::Registering {
+        return Err(ServerError::RegistrationClosed);
+    }
+    if tournament.entrants.len() >= MAX_ENTRANTS {
+        return Err(ServerError::GameFull);
+    }
+    if tournament.entrants.iter().any(|e| e.name == name) {
+        return Err(ServerError::NameTaken);
+    }
+    let token = new_token();
+    tournament.entrants.push(Entrant {
+        name,
+        token: token.clone(),
+    });
+    tournament.touched = Instant::now();
+    let entrant = tournament.entrants.len() - 1;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "entrant": entrant, "token": token })))
+}
+
+/// Close registration and start the first round; needs the organizer's token.
+#[post("/tournament/{tid}/start")]
+pub async fn start_tournament(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> ServerResult<HttpResponse> {
+    let tid = parse_gid(&path.into_inner())?;
+    let mut st = state.lock();
+    let tournament = st
+        .tournaments
+        .get_mut(&tid)
+        .ok_or(ServerError::NoSuchTournament)?;
+    if request_token(&req).as_ref() != Some(&tournament.organizer) {
+        return Err(ServerError::NotOrganizer);
+    }
+    if tournament.stage != Stage::Registering {
+        return Err(ServerError::RegistrationClosed);
+    }
+    if tournament.entrants.len() < 2 {
+        return Err(ServerError::BadOptions(
+            "a tournament needs two entrants".into(),
+        ));
+    }
+    tournament.stage = Stage::Running;
+    let timed = st.advance_tournament(tid);
+    drop(st);
+    for gid in timed {
+        clock::spawn(state.clone(), gid);
+    }
+    spawn(state, tid);
+    Ok(HttpResponse::Ok().body(()))
+}
+
+#[derive(Deserialize)]
+pub struct Decision {
+    /// Counting from 1, as in `/tournament/{tid}/games`.
+    round: usize,
+    /// Index into the round's pairings.
+    pairing: usize,
+    /// The winning entrant, or `None` for a draw in a round robin.
+    winner: Option<usize>,
+}
+
+/// Settle a disputed pairing as JSON `{round, pairing, winner}`; needs the organizer's token.
+#[post("/tournament/{tid}/decide")]
+pub async fn decide(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<Decision>,
+) -> ServerResult<HttpResponse> {
+    let tid = parse_gid(&path.into_inner())?;
+    let decision = body.into_inner();
+    let mut st = state.lock();
+    let tournament = st
+        .tournaments
+        .get_mut(&tid)
+        .ok_or(ServerError::NoSuchTournament)?;
+    if request_token(&req).as_ref() != Some(&tournament.organizer) {
+        return Err(ServerError::NotOrganizer);
+    }
+    let format = tournament.format;
+    let pairing = decision
+        .round
+        .checked_sub(1)
+        .and_then(|round| tournament.rounds.get_mut(round))
+        .and_then(|round| round.get_mut(decision.pairing))
+        .filter(|pairing| pairing.disputed)
+        .ok_or_else(|| ServerError::BadOptions("no such disputed pairing".into()))?;
+    match decision.winner {
+        Some(winner) if winner == pairing.a || Some(winner) == pairing.b => {}
+        None if format == Format::RoundRobin => {}
+        _ => {
+            return Err(ServerError::BadOptions(
+                "the winner must be one of the pairing's entrants".into(),
+            ))
+        }
+    }
+    pairing.winner = decision.winner;
+    pairing.disputed = false;
+    pairing.decided = true;
+    let timed = st.advance_tournament(tid);
+    drop(st);
+    for gid in timed {
+        clock::spawn(state.clone(), gid);
+    }
+    Ok(HttpResponse::Ok().body(()))
+}
+
+/// Standings and every round's pairings so far.
+#[get("/tournament/{tid}")]
+pub async fn tournament_status(
+    state: Data<Mutex<State>>,
+    path: web::Path<String>,
+) -> ServerResult<HttpResponse> {
+    let tid = parse_gid(&path.into_inner())?;
+    let st = state.lock();
+    let tournament = st
+        .tournaments
+        .get(&tid)
+        .ok_or(ServerError::NoSuchTournament)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "name": tournament.name,
+        "format": tournament.format,
+        "map": tournament.map,
+        "stage": tournament.stage,
+        "standings": tournament.standings(),
+        "rounds": tournament.rounds,
+    })))
+}
+
+/// The requesting entrant's seats, `[{round, gid, pid, token}]`, found by the token
+/// `register` gave them.
+#[get("/tournament/{tid}/games")]
+pub async fn entrant_games(
+    state: Data<Mutex<State>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> ServerResult<HttpResponse> {
+    let tid = parse_gid(&path.into_inner())?;
+    let st = state.lock();
+    let tournament = st
+        .tournaments
+        .get(&tid)
+        .ok_or(ServerError::NoSuchTournament)?;
+    let token = request_token(&req);
+    let entrant = tournament
+        .entrants
+        .iter()
+        .position(|e| Some(&e.token) == token.as_ref())
+        .ok_or(ServerError::NotYourSeat)?;
+    let mut seats = vec![];
+    for (round, pairings) in tournament.rounds.iter().enumerate() {
+        for pairing in pairings {
+            let (Some(gid), Some((token_a, token_b))) = (pairing.gid, &pairing.tokens) else {
+                continue;
+            };
+            let seat = if pairing.a == entrant {
+                (0, token_a)
+            } else if pairing.b == Some(entrant) {
+                (1, token_b)
+            } else {
+                continue;
+            };
+            seats.push(serde_json::json!({
+                "round": round + 1,
+                "gid": gid.to_string(),
+                "pid": seat.0,
+                "token": seat.1,
+            }));
+        }
+    }
+    Ok(HttpResponse::Ok().json(seats))
+}
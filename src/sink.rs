@@ -0,0 +1,37 @@
+use crate::{Envelope, Observation, PlayerId};
+
+/// Something that wants to hear about observations as the game produces them.
+pub trait EventSink {
+    /// Called once per observation: `recipient` is `None` for public observations.
+    fn on_observation(&mut self, recipient: Option<PlayerId>, obs: &Observation);
+
+    /// Called for every envelope delivered to a player, public ones included.
+    fn on_envelope(&mut self, _recipient: PlayerId, _envelope: &Envelope) {}
+}
+
+/// The subscribers of a game.
+///
+/// Subscriptions are not carried over by `Clone` (nor serialized): a cloned game is a
+/// simulation, and must not leak what happens in it to the real game's listeners.
+#[derive(Default)]
+pub struct Sinks(pub Vec<Box<dyn EventSink + Send>>);
+
+impl Clone for Sinks {
+    fn clone(&self) -> Self {
+        Sinks::default()
+    }
+}
+
+impl Sinks {
+    pub(crate) fn observation(&mut self, recipient: Option<PlayerId>, obs: &Observation) {
+        for sink in &mut self.0 {
+            sink.on_observation(recipient, obs);
+        }
+    }
+
+    pub(crate) fn envelope(&mut self, recipient: PlayerId, envelope: &Envelope) {
+        for sink in &mut self.0 {
+            sink.on_envelope(recipient, envelope);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::f32::consts::TAU;
+use std::fmt::Write;
+
+use petgraph::visit::EdgeRef;
+
+use crate::{Game, PlayerId};
+
+/// Width and height of the drawing, in SVG user units.
+const SIZE: f32 = 600.0;
+const MARGIN: f32 = 80.0;
+
+impl Game {
+    /// Draw the board as SVG without shelling out to graphviz, laid out from map positions.
+    ///
+    /// Shows the same things as `render`, plus the perspective player's own peg. Boards
+    /// without positions (every location at the origin) are laid out on a circle.
+    pub fn render_svg(&self, perspective: PlayerId) -> String {
+        let n = self.cities.node_count().max(1) as f32;
+        let laid_out = self.cities.node_weights().any(|loc| loc.pos != (0.0, 0.0));
+        let place = |i: usize, pos: (f32, f32)| -> (f32, f32) {
+            let (x, y) = if laid_out {
+                pos
+            } else {
+                let angle = TAU * i as f32 / n;
+                (0.5 + 0.5 * angle.cos(), 0.5 + 0.5 * angle.sin())
+            };
+            (
+                MARGIN + x * (SIZE - 2.0 * MARGIN),
+                MARGIN + y * (SIZE - 2.0 * MARGIN),
+            )
+        };
+        let at = |idx: petgraph::graph::NodeIndex| {
+            let loc = &self.cities[idx];
+            place(idx.index(), loc.pos)
+        };
+
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {SIZE} {SIZE}" width="{SIZE}" height="{SIZE}" font-family="sans-serif" font-size="14">"#
+        );
+        for edge in self.cities.edge_references() {
+            let (x1, y1) = at(edge.source());
+            let (x2, y2) = at(edge.target());
+            let _ = write!(
+                svg,
+                r#"<line x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}" stroke="black"/>"#
+            );
+        }
+        for location in self.cities.node_weights() {
+            let (x, y) = at(location.index);
+            let r = 18.0 + 6.0 * location.base_income as f32;
+            let color = match location.control {
+                Some(idx) => self.color(idx),
+                None => "white",
+            };
+            let owner = location
+                .control
+                .map(|idx| self.name(idx))
+                .unwrap_or_default();
+            let pending_powerup = location
+                .pending_powerup
+                .map(|x| x.to_string())
+                .unwrap_or_default();
+            let boost = if location.boost { "⚡" } else { "" };
+            let garrison = "🛡".repeat(location.garrison as usize);
+            let _ = write!(
+                svg,
+                r#"<g><title>{}</title><circle cx="{x}" cy="{y}" r="{r}" fill="{}" stroke="black"/><text x="{x}" y="{y}" text-anchor="middle" dominant-baseline="central">{pending_powerup}{boost}{garrison}</text><text x="{x}" y="{}" text-anchor="middle">{}</text></g>"#,
+                escape(&owner),
+                escape(color),
+                y + r + 16.0,
+                escape(&location.name),
+            );
+        }
+        if let Some(me) = self.players.get(perspective).filter(|p| p.alive) {
+            if self.cities.node_weight(me.location).is_some() {
+                let (x, y) = at(me.location);
+                let _ = write!(
+                    svg,
+                    r#"<circle cx="{x}" cy="{y}" r="7" fill="{}" stroke="black" stroke-width="2"><title>you</title></circle>"#,
+                    escape(self.color(perspective)),
+                );
+            }
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,96 @@
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+
+use crate::{Game, GameError, Intel, Player, PlayerId};
+
+/// The board as one player is entitled to see it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameView {
+    pub perspective: PlayerId,
+    pub turn: u32,
+    pub alert: u32,
+    /// The viewing player's own, complete state.
+    pub me: Player,
+    pub locations: Vec<LocationView>,
+    pub opponents: Vec<OpponentView>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocationView {
+    pub index: NodeIndex,
+    pub name: String,
+    pub base_income: Intel,
+    pub pending_powerup: Option<Intel>,
+    pub boost: bool,
+    pub neighbors: Vec<NodeIndex>,
+    /// Believed controller, if the viewer knows anything about it.
+    pub control: Option<PlayerId>,
+    /// Turns since `control` was confirmed; `None` if never seen.
+    pub control_age: Option<u32>,
+    /// Garrison strength, only shown for the viewer's own locations.
+    pub garrison: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpponentView {
+    pub id: PlayerId,
+    pub resigned: bool,
+    /// Last known location, if any.
+    pub last_seen: Option<NodeIndex>,
+    /// Turns since `last_seen` was confirmed.
+    pub age: Option<u32>,
+    /// Where they were last heard leaving, if they haven't been placed since.
+    #[serde(default)]
+    pub departed: Option<NodeIndex>,
+}
+
+impl Game {
+    /// A fog-of-war view of the game for `pid`, built from what they know, or `NoSuchPlayer`.
+    pub fn view_for(&self, pid: PlayerId) -> Result<GameView, GameError> {
+        if pid >= self.players.len() {
+            return Err(GameError::NoSuchPlayer);
+        }
+        let knowledge = self.knowledge(pid);
+        let locations = self
+            .cities
+            .node_weights()
+            .map(|loc| {
+                let belief = knowledge.control.get(&loc.index);
+                LocationView {
+                    index: loc.index,
+                    name: loc.name.clone(),
+                    base_income: loc.base_income,
+                    pending_powerup: loc.pending_powerup,
+                    boost: loc.boost,
+                    neighbors: self.cities.neighbors(loc.index).collect(),
+                    control: belief.and_then(|k| k.value),
+                    control_age: belief.map(|k| k.age(self.turn)),
+                    garrison: (loc.control == Some(pid)).then_some(loc.garrison),
+                }
+            })
+            .collect();
+        let opponents = self
+            .players
+            .iter()
+            .filter(|p| p.id != pid)
+            .map(|p| {
+                let seen = knowledge.last_seen.get(&p.id);
+                OpponentView {
+                    id: p.id,
+                    resigned: p.resigned,
+                    last_seen: seen.map(|k| k.value),
+                    age: seen.map(|k| k.age(self.turn)),
+                    departed: knowledge.departed.get(&p.id).map(|k| k.value),
+                }
+            })
+            .collect();
+        Ok(GameView {
+            perspective: pid,
+            turn: self.turn,
+            alert: self.alert,
+            me: self.players[pid],
+            locations,
+            opponents,
+        })
+    }
+}
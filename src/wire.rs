@@ -0,0 +1,399 @@
+//! Compact binary encoding of observations and events for the wire.
+//!
+//! The format is deliberately independent of the in-memory enums: every tag below is frozen.
+//! New variants get new tags, and an existing tag never changes meaning or field order, so a
+//! peer can always decode anything with a known tag regardless of how the Rust types evolve.
+//!
+//! Integers are unsigned LEB128 varints. `Option<n>` is encoded as `0` for `None` and `n + 1`
+//! otherwise. Booleans are a single `0`/`1` byte.
+
+use petgraph::graph::NodeIndex;
+
+use crate::{Envelope, Event, IntelKind, Observation, Perception, Phase};
+
+/// Version byte that starts every encoded `Event`.
+///
+/// Version 2 added the causing action id to envelopes. Version 1 still decodes, with no cause.
+pub const WIRE_VERSION: u8 = 2;
+/// The oldest version that still decodes.
+const OLDEST_WIRE_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireError {
+    /// The input ended in the middle of a value.
+    Truncated,
+    /// A varint did not fit in 64 bits.
+    Overflow,
+    /// An unknown tag for the named kind of value.
+    BadTag(&'static str, u64),
+    /// An event encoded with an unsupported format version.
+    BadVersion(u8),
+    /// A custom observation's key or data was not valid UTF-8 / JSON.
+    BadPayload,
+}
+
+pub type WireResult<T> = Result<T, WireError>;
+
+fn put(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn put_opt(out: &mut Vec<u8>, n: Option<u64>) {
+    put(out, n.map_or(0, |n| n + 1));
+}
+
+fn put_bool(out: &mut Vec<u8>, b: bool) {
+    out.push(b as u8);
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn take_str(input: &mut &[u8]) -> WireResult<String> {
+    let len = take(input)? as usize;
+    if input.len() < len {
+        return Err(WireError::Truncated);
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    String::from_utf8(bytes.to_vec()).map_err(|_| WireError::BadPayload)
+}
+
+fn take(input: &mut &[u8]) -> WireResult<u64> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first().ok_or(WireError::Truncated)?;
+        *input = rest;
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(WireError::Overflow)
+}
+
+fn take_opt(input: &mut &[u8]) -> WireResult<Option<u64>> {
+    Ok(take(input)?.checked_sub(1))
+}
+
+fn take_bool(input: &mut &[u8]) -> WireResult<bool> {
+    let (&byte, rest) = input.split_first().ok_or(WireError::Truncated)?;
+    *input = rest;
+    Ok(byte != 0)
+}
+
+fn node(n: u64) -> NodeIndex {
+    NodeIndex::new(n as usize)
+}
+
+fn idx(at: NodeIndex) -> u64 {
+    at.index() as u64
+}
+
+fn intel_tag(kind: IntelKind) -> u64 {
+    match kind {
+        IntelKind::HideSignals => 0,
+        IntelKind::Reveal => 1,
+        IntelKind::Invisible => 2,
+        IntelKind::Prepare => 3,
+        IntelKind::Reinforce => 4,
+        IntelKind::Informant => 5,
+    }
+}
+
+fn intel_kind(tag: u64) -> WireResult<IntelKind> {
+    Ok(match tag {
+        0 => IntelKind::HideSignals,
+        1 => IntelKind::Reveal,
+        2 => IntelKind::Invisible,
+        3 => IntelKind::Prepare,
+        4 => IntelKind::Reinforce,
+        5 => IntelKind::Informant,
+        _ => return Err(WireError::BadTag("IntelKind", tag)),
+    })
+}
+
+fn perception_tag(how: Perception) -> u64 {
+    match how {
+        Perception::Seen => 0,
+        Perception::Heard => 1,
+        Perception::Scanned => 2,
+    }
+}
+
+fn perception(tag: u64) -> WireResult<Perception> {
+    Ok(match tag {
+        0 => Perception::Seen,
+        1 => Perception::Heard,
+        2 => Perception::Scanned,
+        _ => return Err(WireError::BadTag("Perception", tag)),
+    })
+}
+
+fn phase_tag(phase: Phase) -> u64 {
+    match phase {
+        Phase::Setup => 0,
+        Phase::Upkeep => 1,
+        Phase::Action => 2,
+    }
+}
+
+fn phase(tag: u64) -> WireResult<Phase> {
+    Ok(match tag {
+        0 => Phase::Setup,
+        1 => Phase::Upkeep,
+        2 => Phase::Action,
+        _ => return Err(WireError::BadTag("Phase", tag)),
+    })
+}
+
+impl Observation {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let pid = |p: usize| p as u64;
+        match *self {
+            Observation::Death { by, of } => {
+                put(out, 0);
+                put(out, pid(by));
+                put(out, pid(of));
+            }
+            Observation::Strike { by, at, prepared } => {
+                put(out, 1);
+                put_opt(out, by.map(pid));
+                put_opt(out, at.map(idx));
+                put_bool(out, prepared);
+            }
+            Observation::WaitMove { by } => {
+                put(out, 2);
+                put_opt(out, by.map(pid));
+            }
+            Observation::Capture { by, at } => {
+                put(out, 3);
+                put(out, pid(by));
+                put(out, idx(at));
+            }
+            Observation::Intel { by, kind } => {
+                put(out, 4);
+                put_opt(out, by.map(pid));
+                put_opt(out, kind.map(intel_tag));
+            }
+            Observation::Reveal { who, at } => {
+                put(out, 5);
+                put(out, pid(who));
+                put(out, idx(at));
+            }
+            Observation::RevealFailure { who } => {
+                put(out, 6);
+                put(out, pid(who));
+            }
+            Observation::Garrison { at, strength } => {
+                put(out, 7);
+                put(out, idx(at));
+                put(out, strength as u64);
+            }
+            Observation::Alert { level } => {
+                put(out, 8);
+                put(out, level as u64);
+            }
+            Observation::Resign { who } => {
+                put(out, 9);
+                put(out, pid(who));
+            }
+            Observation::Sighting { who, at, as_of } => {
+                put(out, 10);
+                put(out, pid(who));
+                put(out, idx(at));
+                put(out, as_of as u64);
+            }
+            Observation::Rumor { who, at: [a, b] } => {
+                put(out, 11);
+                put(out, pid(who));
+                put(out, idx(a));
+                put(out, idx(b));
+            }
+            Observation::Move { who, from, to, how } => {
+                put(out, 12);
+                put(out, pid(who));
+                put_opt(out, from.map(idx));
+                put_opt(out, to.map(idx));
+                put(out, perception_tag(how));
+            }
+            Observation::Custom { ref key, ref data } => {
+                put(out, 13);
+                put_str(out, key);
+                put_str(out, &data.to_string());
+            }
+            Observation::TurnStart { who, actions } => {
+                put(out, 14);
+                put(out, pid(who));
+                put(out, actions as u64);
+            }
+            Observation::TimedOut { who } => {
+                put(out, 15);
+                put(out, pid(who));
+            }
+        }
+    }
+
+    pub fn decode(input: &mut &[u8]) -> WireResult<Observation> {
+        let tag = take(input)?;
+        let mut pid = || take(input).map(|p| p as usize);
+        Ok(match tag {
+            0 => Observation::Death {
+                by: pid()?,
+                of: pid()?,
+            },
+            1 => Observation::Strike {
+                by: take_opt(input)?.map(|p| p as usize),
+                at: take_opt(input)?.map(node),
+                prepared: take_bool(input)?,
+            },
+            2 => Observation::WaitMove {
+                by: take_opt(input)?.map(|p| p as usize),
+            },
+            3 => Observation::Capture {
+                by: pid()?,
+                at: node(take(input)?),
+            },
+            4 => Observation::Intel {
+                by: take_opt(input)?.map(|p| p as usize),
+                kind: take_opt(input)?.map(intel_kind).transpose()?,
+            },
+            5 => Observation::Reveal {
+                who: pid()?,
+                at: node(take(input)?),
+            },
+            6 => Observation::RevealFailure { who: pid()? },
+            7 => Observation::Garrison {
+                at: node(take(input)?),
+                strength: take(input)? as u32,
+            },
+            8 => Observation::Alert {
+                level: take(input)? as u32,
+            },
+            9 => Observation::Resign { who: pid()? },
+            10 => Observation::Sighting {
+                who: pid()?,
+                at: node(take(input)?),
+                as_of: take(input)? as u32,
+            },
+            11 => Observation::Rumor {
+                who: pid()?,
+                at: [node(take(input)?), node(take(input)?)],
+            },
+            12 => Observation::Move {
+                who: pid()?,
+                from: take_opt(input)?.map(node),
+                to: take_opt(input)?.map(node),
+                how: perception(take(input)?)?,
+            },
+            13 => Observation::Custom {
+                key: take_str(input)?,
+                data: serde_json::from_str(&take_str(input)?).map_err(|_| WireError::BadPayload)?,
+            },
+            14 => Observation::TurnStart {
+                who: pid()?,
+                actions: take(input)? as u32,
+            },
+            15 => Observation::TimedOut { who: pid()? },
+            _ => return Err(WireError::BadTag("Observation", tag)),
+        })
+    }
+}
+
+impl Envelope {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        put(out, self.turn as u64);
+        put(out, phase_tag(self.phase));
+        put(out, self.seq);
+        put_bool(out, self.public);
+        put(out, self.count as u64);
+        put_opt(out, self.cause);
+        self.obs.encode(out);
+    }
+
+    pub fn decode(input: &mut &[u8]) -> WireResult<Envelope> {
+        Envelope::decode_version(input, WIRE_VERSION)
+    }
+
+    /// Decode an envelope encoded by format `version`.
+    fn decode_version(input: &mut &[u8], version: u8) -> WireResult<Envelope> {
+        Ok(Envelope {
+            turn: take(input)? as u32,
+            phase: phase(take(input)?)?,
+            seq: take(input)?,
+            public: take_bool(input)?,
+            count: take(input)? as u32,
+            cause: if version >= 2 { take_opt(input)? } else { None },
+            obs: Observation::decode(input)?,
+        })
+    }
+}
+
+/// Encode one player's stream of envelopes, prefixed with `WIRE_VERSION`.
+pub fn encode_envelopes(envelopes: &[Envelope]) -> Vec<u8> {
+    let mut out = vec![WIRE_VERSION];
+    put(&mut out, envelopes.len() as u64);
+    for envelope in envelopes {
+        envelope.encode(&mut out);
+    }
+    out
+}
+
+pub fn decode_envelopes(mut input: &[u8]) -> WireResult<Vec<Envelope>> {
+    let input = &mut input;
+    let version = version(input)?;
+    (0..take(input)?)
+        .map(|_| Envelope::decode_version(input, version))
+        .collect()
+}
+
+fn version(input: &mut &[u8]) -> WireResult<u8> {
+    let (&version, rest) = input.split_first().ok_or(WireError::Truncated)?;
+    if !(OLDEST_WIRE_VERSION..=WIRE_VERSION).contains(&version) {
+        return Err(WireError::BadVersion(version));
+    }
+    *input = rest;
+    Ok(version)
+}
+
+impl Event {
+    /// Encode the whole event, prefixed with `WIRE_VERSION`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![WIRE_VERSION];
+        put(&mut out, self.observations.len() as u64);
+        for (&pid, envelopes) in &self.observations {
+            put(&mut out, pid as u64);
+            put(&mut out, envelopes.len() as u64);
+            for envelope in envelopes {
+                envelope.encode(&mut out);
+            }
+        }
+        put(&mut out, self.public_observations.len() as u64);
+        for obs in &self.public_observations {
+            obs.encode(&mut out);
+        }
+        out
+    }
+
+    pub fn decode(mut input: &[u8]) -> WireResult<Event> {
+        let input = &mut input;
+        let version = version(input)?;
+        let mut event = Event::default();
+        for _ in 0..take(input)? {
+            let pid = take(input)? as usize;
+            for _ in 0..take(input)? {
+                event.note(pid, Envelope::decode_version(input, version)?);
+            }
+        }
+        for _ in 0..take(input)? {
+            event.broadcast(Observation::decode(input)?);
+        }
+        Ok(event)
+    }
+}